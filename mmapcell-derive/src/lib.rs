@@ -0,0 +1,159 @@
+//! Derive macros for [`mmapcell::MmapSafe`](https://docs.rs/mmapcell/latest/mmapcell/trait.MmapSafe.html)
+//! and [`mmapcell::MmapPod`](https://docs.rs/mmapcell/latest/mmapcell/trait.MmapPod.html).
+//!
+//! Use it through the `mmapcell` crate (with its default `derive` feature)
+//! rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields};
+
+/// Derives `MmapSafe` for a struct.
+///
+/// The struct must:
+/// - be `#[repr(C)]` or `#[repr(transparent)]`
+/// - not be generic
+/// - only have fields that are `MmapSafe` themselves (which rules out
+///   pointers, references, `bool`, `char` and friends)
+/// - not contain any padding bytes
+///
+/// All of these are checked at compile time.
+#[proc_macro_derive(MmapSafe)]
+pub fn derive_mmap_safe(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `MmapPod` for a struct that is also `MmapSafe`.
+///
+/// The struct must not be generic and only have fields that are `MmapPod`
+/// themselves (which rules out atomics and anything else with interior
+/// mutability). This is checked at compile time.
+#[proc_macro_derive(MmapPod)]
+pub fn derive_mmap_pod(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_pod(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let field_types = field_types(&input, "MmapSafe")?;
+
+    check_repr(&input)?;
+
+    // spanned so that a bad field gets the error rather than the derive
+    let field_asserts = field_types.iter().map(|ty| {
+        quote_spanned! {ty.span()=>
+            __assert_mmap_safe::<#ty>();
+        }
+    });
+
+    let padding_msg = format!("`{name}` contains padding bytes and can't be MmapSafe");
+
+    Ok(quote! {
+        const _: () = {
+            fn __assert_mmap_safe<T: ::mmapcell::MmapSafe + ?Sized>() {}
+
+            const _: fn() = || {
+                #(#field_asserts)*
+            };
+
+            assert!(
+                ::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#field_types>())*,
+                #padding_msg
+            );
+        };
+
+        unsafe impl ::mmapcell::MmapSafe for #name {}
+    })
+}
+
+fn expand_pod(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let field_types = field_types(&input, "MmapPod")?;
+
+    let field_asserts = field_types.iter().map(|ty| {
+        quote_spanned! {ty.span()=>
+            __assert_mmap_pod::<#ty>();
+        }
+    });
+
+    // layout and padding are left to the MmapSafe supertrait
+    Ok(quote! {
+        const _: () = {
+            fn __assert_mmap_pod<T: ::mmapcell::MmapPod + ?Sized>() {}
+
+            const _: fn() = || {
+                #(#field_asserts)*
+            };
+        };
+
+        unsafe impl ::mmapcell::MmapPod for #name {}
+    })
+}
+
+/// The types of every field of a non-generic struct.
+fn field_types<'a>(input: &'a DeriveInput, derive: &str) -> Result<Vec<&'a syn::Type>, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(_) => {
+            let msg = format!(
+                "{derive} can't be derived for enums, not every bit pattern is a valid variant"
+            );
+            return Err(Error::new(Span::call_site(), msg));
+        }
+        Data::Union(_) => {
+            let msg = format!("{derive} can't be derived for unions");
+            return Err(Error::new(Span::call_site(), msg));
+        }
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            format!("{derive} can't be derived for generic types, implement it by hand instead"),
+        ));
+    }
+
+    Ok(match fields {
+        Fields::Named(f) => f.named.iter().map(|f| &f.ty).collect(),
+        Fields::Unnamed(f) => f.unnamed.iter().map(|f| &f.ty).collect(),
+        Fields::Unit => Vec::new(),
+    })
+}
+
+fn check_repr(input: &DeriveInput) -> Result<(), Error> {
+    let mut stable = false;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("C") || meta.path.is_ident("transparent") {
+                stable = true;
+            }
+
+            // skip over the arguments of things like align(8) and packed(2)
+            if meta.input.peek(syn::token::Paren) {
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+
+            Ok(())
+        })?;
+    }
+
+    if !stable {
+        return Err(Error::new(
+            input.ident.span(),
+            "MmapSafe requires #[repr(C)] or #[repr(transparent)] for a stable layout",
+        ));
+    }
+
+    Ok(())
+}
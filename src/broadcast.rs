@@ -0,0 +1,436 @@
+use std::{
+    path::Path,
+    sync::atomic::{self, AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, ring::CachePadded, LayoutError, MmapCellError, MmapHeaderSlice, MmapSafe};
+
+#[repr(C)]
+struct BroadcastHeader {
+    capacity: u32,
+    slot_size: u32,
+    /// how many messages have ever been published
+    written: AtomicU64,
+    _pad: [u64; 6],
+    /// low half of `written`, for readers to sleep on
+    notify: CachePadded,
+}
+
+// SAFETY: plain words filling two cache lines, capacities and slot sizes
+// that don't match the file are rejected on open
+unsafe impl MmapSafe for BroadcastHeader {}
+
+/// Every slot starts with the message's sequence word and its length,
+/// followed by `slot_size` bytes of message.
+const SLOT_HEADER: usize = 16;
+
+/// A reader fell so far behind that messages it hadn't read yet were overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("reader was lapped and missed {missed} messages")]
+pub struct Lapped {
+    /// how many messages were skipped to catch up
+    pub missed: u64,
+}
+
+/// A broadcast log in a file, one writer and any number of readers.
+///
+/// The writer never waits for anybody, it just keeps overwriting the oldest of
+/// `capacity` slots. Each [`BroadcastReader`] has a cursor of its own and finds out
+/// through [`Lapped`] when it fell more than `capacity` messages behind, so readers
+/// can come and go at any time without the writer knowing about them.
+///
+/// Messages are byte strings of up to `slot_size` bytes each.
+///
+/// Nothing stops two writers from using the same file at once,
+/// but the log falls apart if they do.
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapBroadcast;
+///
+/// let path = "/tmp/mmapcell-broadcast-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+///
+/// let mut writer = MmapBroadcast::open(path, 64, 32).unwrap();
+///
+/// let feed = MmapBroadcast::open(path, 64, 32).unwrap();
+/// let mut reader = feed.subscribe();
+///
+/// writer.publish(b"AAPL 189.20");
+/// writer.publish(b"MSFT 411.05");
+///
+/// assert_eq!(reader.try_recv().unwrap().as_deref(), Some(&b"AAPL 189.20"[..]));
+/// assert_eq!(reader.try_recv().unwrap().as_deref(), Some(&b"MSFT 411.05"[..]));
+/// assert_eq!(reader.try_recv().unwrap(), None);
+/// ```
+pub struct MmapBroadcast {
+    slice: MmapHeaderSlice<BroadcastHeader, u64>,
+}
+
+impl MmapBroadcast {
+    /// Opens the log at `path`, creating an empty one with `capacity` slots of
+    /// `slot_size` bytes if the file doesn't exist yet.
+    ///
+    /// Both have to match the existing log, if any.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        capacity: u32,
+        slot_size: u32,
+    ) -> Result<MmapBroadcast, MmapCellError> {
+        if capacity == 0 {
+            return Err(LayoutError::BadCapacity { capacity: 0 }.into());
+        }
+
+        let path = path.as_ref();
+        let count = |h: &BroadcastHeader| h.capacity as usize * stride(h.slot_size) / 8;
+
+        let header = BroadcastHeader {
+            capacity,
+            slot_size,
+            written: AtomicU64::new(0),
+            _pad: [0; 6],
+            notify: CachePadded::default(),
+        };
+
+        let slice = MmapHeaderSlice::open_or_create(path, header, count)?;
+
+        let header = slice.header();
+        if header.capacity != capacity {
+            return Err(LayoutError::BadCapacity {
+                capacity: header.capacity as usize,
+            }
+            .into());
+        }
+
+        if header.slot_size != slot_size {
+            return Err(LayoutError::SlotSizeMismatch {
+                expected: slot_size as usize,
+                found: header.slot_size as usize,
+            }
+            .into());
+        }
+
+        Ok(MmapBroadcast { slice })
+    }
+
+    /// Appends `message` and wakes any readers waiting for it,
+    /// returning its sequence number.
+    ///
+    /// # Panics
+    /// if `message` is longer than the slot size
+    pub fn publish(&mut self, message: &[u8]) -> u64 {
+        let header = self.slice.header();
+
+        assert!(
+            message.len() <= header.slot_size as usize,
+            "message of {} bytes doesn't fit in a {} byte slot",
+            message.len(),
+            header.slot_size
+        );
+
+        let seq = header.written.load(Ordering::Relaxed);
+        let slot = self.slot(seq);
+
+        // SAFETY: slots are 8 byte aligned (the stride and the header are multiples
+        // of 8) and every one is SLOT_HEADER + slot_size bytes long
+        unsafe {
+            let state = &*slot.cast::<AtomicU64>();
+
+            // odd while it's being written, so readers can tell it was torn
+            state.store(seq * 2 + 1, Ordering::Relaxed);
+            atomic::fence(Ordering::Release);
+
+            slot.add(8).cast::<u32>().write(message.len() as u32);
+            slot.add(SLOT_HEADER)
+                .copy_from_nonoverlapping(message.as_ptr(), message.len());
+
+            state.store(seq * 2 + 2, Ordering::Release);
+        }
+
+        header.written.store(seq + 1, Ordering::Release);
+
+        header
+            .notify
+            .value
+            .store((seq + 1) as u32, Ordering::SeqCst);
+        if header.notify.waiting.load(Ordering::SeqCst) != 0 {
+            futex::wake_all(&header.notify.value);
+        }
+
+        seq
+    }
+
+    /// A reader that starts with the next message published.
+    pub fn subscribe(&self) -> BroadcastReader<'_> {
+        BroadcastReader {
+            log: self,
+            cursor: self.written(),
+        }
+    }
+
+    /// A reader that starts with the oldest message that hasn't been overwritten yet.
+    pub fn subscribe_from_oldest(&self) -> BroadcastReader<'_> {
+        BroadcastReader {
+            log: self,
+            cursor: self.written().saturating_sub(self.capacity() as u64),
+        }
+    }
+
+    /// How many messages have ever been published.
+    pub fn written(&self) -> u64 {
+        self.slice.header().written.load(Ordering::Acquire)
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.slice.header().capacity
+    }
+
+    pub fn slot_size(&self) -> u32 {
+        self.slice.header().slot_size
+    }
+
+    /// The slot message `seq` lands in. Readers copy slots out while the writer is
+    /// filling them, so they're only ever touched through raw pointers and never borrowed.
+    fn slot(&self, seq: u64) -> *mut u8 {
+        let header = self.slice.header();
+        let index = (seq % header.capacity as u64) as usize;
+
+        // SAFETY: the capacity and slot size were checked against the file on open
+        unsafe {
+            self.slice
+                .entries_ptr()
+                .cast::<u8>()
+                .add(index * stride(header.slot_size))
+        }
+    }
+
+    /// Copies out message `seq`, or if it was overwritten (or torn) in the meantime
+    /// returns the sequence number of the message that took over its slot.
+    fn read(&self, seq: u64) -> Result<Vec<u8>, u64> {
+        let header = self.slice.header();
+        let slot = self.slot(seq);
+
+        // SAFETY: see publish, the copy may race with the writer but
+        // it's thrown away unless the sequence word says it didn't
+        unsafe {
+            let state = &*slot.cast::<AtomicU64>();
+
+            match state.load(Ordering::Acquire) {
+                current if current != seq * 2 + 2 => return Err(slot_seq(current)),
+                _ => {}
+            }
+
+            let len =
+                (slot.add(8).cast::<u32>().read_volatile() as usize).min(header.slot_size as usize);
+            let mut message = vec![0; len];
+            message
+                .as_mut_ptr()
+                .copy_from_nonoverlapping(slot.add(SLOT_HEADER), len);
+
+            atomic::fence(Ordering::Acquire);
+
+            match state.load(Ordering::Relaxed) {
+                current if current == seq * 2 + 2 => Ok(message),
+                current => Err(slot_seq(current)),
+            }
+        }
+    }
+}
+
+/// Which message a slot's sequence word belongs to, whether it's done being written or not.
+fn slot_seq(state: u64) -> u64 {
+    state.saturating_sub(1) / 2
+}
+
+/// How far apart slots are, rounded up to keep each one 8 byte aligned.
+fn stride(slot_size: u32) -> usize {
+    (SLOT_HEADER + slot_size as usize).next_multiple_of(8)
+}
+
+/// One reader's position in a [`MmapBroadcast`].
+pub struct BroadcastReader<'a> {
+    log: &'a MmapBroadcast,
+    cursor: u64,
+}
+
+impl BroadcastReader<'_> {
+    /// Sequence number of the next message this reader will return.
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Moves to message `cursor`, say to pick up where a previous reader left off.
+    pub fn seek(&mut self, cursor: u64) {
+        self.cursor = cursor;
+    }
+
+    /// The next message, or `None` if the reader is caught up.
+    ///
+    /// A reader that's been lapped gets [`Lapped`] once and then carries on
+    /// from the oldest message that's still there.
+    pub fn try_recv(&mut self) -> Result<Option<Vec<u8>>, Lapped> {
+        let capacity = self.log.capacity() as u64;
+        let written = self.log.written();
+
+        if self.cursor >= written {
+            return Ok(None);
+        }
+
+        let oldest = written.saturating_sub(capacity);
+
+        if self.cursor < oldest {
+            return Err(self.skip_to(oldest));
+        }
+
+        match self.log.read(self.cursor) {
+            Ok(message) => {
+                self.cursor += 1;
+                Ok(Some(message))
+            }
+            // overwritten by a later lap, maybe by a writer that died before it
+            // could move `written` on, so go by the slot rather than `written`
+            Err(newer) if newer > self.cursor => {
+                Err(self.skip_to((newer + 1).saturating_sub(capacity).max(self.cursor + 1)))
+            }
+            // older than a message that was published, the file's been tampered with
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Moves on to `oldest` after being lapped.
+    fn skip_to(&mut self, oldest: u64) -> Lapped {
+        let missed = oldest - self.cursor;
+        self.cursor = oldest;
+
+        Lapped { missed }
+    }
+
+    /// Like [`try_recv`](BroadcastReader::try_recv) but waits for the next message
+    /// for up to `timeout`, or forever if it's `None`.
+    pub fn recv(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<u8>>, Lapped> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let notify = &self.log.slice.header().notify;
+
+        loop {
+            let seen = notify.value.load(Ordering::SeqCst);
+
+            if let Some(message) = self.try_recv()? {
+                return Ok(Some(message));
+            }
+
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+                    left if left.is_zero() => return Ok(None),
+                    left => Some(left),
+                },
+            };
+
+            wait(&notify.value, &notify.waiting, seen, timeout);
+        }
+    }
+}
+
+/// Sleeps on `value` while it's `seen`, counted in `waiting` so the writer
+/// only makes the syscall when there's somebody to wake.
+fn wait(value: &AtomicU32, waiting: &AtomicU32, seen: u32, timeout: Option<Duration>) {
+    waiting.fetch_add(1, Ordering::SeqCst);
+
+    if value.load(Ordering::SeqCst) == seen {
+        futex::wait(value, seen, timeout);
+    }
+
+    waiting.fetch_sub(1, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_get_lapped() {
+        let path = std::env::temp_dir().join("mmapcell-broadcast-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut writer = MmapBroadcast::open(&path, 4, 8).unwrap();
+        assert!(matches!(
+            MmapBroadcast::open(&path, 8, 8),
+            Err(MmapCellError::Layout(LayoutError::BadCapacity {
+                capacity: 4
+            }))
+        ));
+        assert!(matches!(
+            MmapBroadcast::open(&path, 4, 16),
+            Err(MmapCellError::Layout(LayoutError::SlotSizeMismatch {
+                expected: 16,
+                found: 8
+            }))
+        ));
+
+        let feed = MmapBroadcast::open(&path, 4, 8).unwrap();
+        let mut slow = feed.subscribe();
+
+        for i in 0..6u64 {
+            writer.publish(&i.to_le_bytes());
+        }
+
+        // messages 0 and 1 were overwritten before the reader got to them
+        assert_eq!(slow.try_recv(), Err(Lapped { missed: 2 }));
+        assert_eq!(slow.try_recv(), Ok(Some(2u64.to_le_bytes().to_vec())));
+
+        // late joiners see what's still there
+        let mut late = feed.subscribe_from_oldest();
+        assert_eq!(late.cursor(), 2);
+        assert_eq!(late.try_recv(), Ok(Some(2u64.to_le_bytes().to_vec())));
+
+        let waiter = std::thread::spawn({
+            let path = path.clone();
+
+            move || {
+                let feed = MmapBroadcast::open(&path, 4, 8).unwrap();
+                let mut reader = feed.subscribe();
+
+                reader.recv(Some(Duration::from_secs(5))).unwrap()
+            }
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        writer.publish(b"wake up");
+
+        assert_eq!(waiter.join().unwrap(), Some(b"wake up".to_vec()));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_writer_mid_publish() {
+        let path = std::env::temp_dir().join("mmapcell-broadcast-dead-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut writer = MmapBroadcast::open(&path, 4, 8).unwrap();
+
+        for i in 0..4u64 {
+            writer.publish(&i.to_le_bytes());
+        }
+
+        // the writer died halfway through message 4, its slot is left odd
+        // and `written` never moves past it
+        let state = unsafe { &*writer.slot(4).cast::<AtomicU64>() };
+        state.store(4 * 2 + 1, Ordering::Relaxed);
+        drop(writer);
+
+        let feed = MmapBroadcast::open(&path, 4, 8).unwrap();
+        let mut reader = feed.subscribe_from_oldest();
+
+        assert_eq!(reader.try_recv(), Err(Lapped { missed: 1 }));
+
+        for i in 1..4u64 {
+            assert_eq!(reader.try_recv(), Ok(Some(i.to_le_bytes().to_vec())));
+        }
+
+        assert_eq!(reader.try_recv(), Ok(None));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,101 @@
+//! Safe constructors and byte views for [`bytemuck::Pod`] types.
+//!
+//! Enabled with the `bytemuck` feature. These mirror the [`MmapCell`] constructors
+//! for types that already derive `Pod` instead of [`MmapSafe`](crate::MmapSafe).
+//!
+//! ```rust
+//! use mmapcell::bytemuck;
+//!
+//! let mut cell = bytemuck::new_anon::<[u32; 4]>().unwrap();
+//! cell.get_mut()[0] = 1;
+//!
+//! assert_eq!(bytemuck::bytes_of(&cell)[..4], 1u32.to_ne_bytes());
+//! ```
+
+use ::bytemuck::Pod;
+use memmap2::MmapOptions;
+use std::path::Path;
+
+use crate::{MmapCell, MmapCellError, SizePolicy};
+
+/// Like [`MmapCell::new_anon`] for a `Pod` type.
+pub fn new_anon<T: Pod>() -> Result<MmapCell<T>, MmapCellError> {
+    let m = MmapOptions::new().len(size_of::<T>()).map_anon()?;
+
+    // SAFETY: Pod types are valid for any bytes
+    unsafe { MmapCell::new_unchecked(m, SizePolicy::default()) }
+}
+
+/// Like [`MmapCell::new_named`] for a `Pod` type.
+pub fn new_named<T: Pod, P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+    let options = MmapCell::options().create(true).resize(true);
+
+    // SAFETY: Pod types are valid for any bytes
+    unsafe { options.open_unchecked(path) }
+}
+
+/// Like [`MmapCell::open_named`] for a `Pod` type.
+pub fn open_named<T: Pod, P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+    open_named_with_policy(path, SizePolicy::default())
+}
+
+/// Like [`MmapCell::open_named_with_policy`] for a `Pod` type.
+pub fn open_named_with_policy<T: Pod, P: AsRef<Path>>(
+    path: P,
+    policy: SizePolicy,
+) -> Result<MmapCell<T>, MmapCellError> {
+    let options = MmapCell::options().size_policy(policy);
+
+    // SAFETY: Pod types are valid for any bytes
+    unsafe { options.open_unchecked(path) }
+}
+
+/// The bytes of the mapped `T`.
+pub fn bytes_of<T: Pod>(cell: &MmapCell<T>) -> &[u8] {
+    // SAFETY: Pod types have no padding
+    unsafe { cell.bytes_unchecked() }
+}
+
+/// The bytes of the mapped `T`, writable.
+pub fn bytes_of_mut<T: Pod>(cell: &mut MmapCell<T>) -> &mut [u8] {
+    // SAFETY: Pod types have no padding and are valid for any bytes
+    unsafe { cell.bytes_mut_unchecked() }
+}
+
+/// Reinterprets a cell as another `Pod` type of the same size,
+/// most usefully to and from a `[u8; N]` byte view.
+///
+/// Fails to compile if `A` and `B` differ in size and returns an
+/// error (dropping the cell) if the mapping isn't aligned for `B`.
+pub fn cast<A: Pod, B: Pod>(cell: MmapCell<A>) -> Result<MmapCell<B>, MmapCellError> {
+    // SAFETY: the bytes of any Pod type are a valid Pod type
+    unsafe { cell.cast_unchecked() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pod_byte_view_roundtrip() {
+        let path = std::env::temp_dir().join("mmapcell-bytemuck-test.bin");
+
+        let mut cell = new_named::<[u16; 2], _>(&path).unwrap();
+        *cell.get_mut() = [0x0102, 0x0304];
+
+        let bytes = cast::<_, [u8; 4]>(cell).unwrap();
+        assert_eq!(
+            *bytes.get(),
+            ::bytemuck::cast::<[u16; 2], [u8; 4]>([0x0102, 0x0304])
+        );
+
+        let cell = cast::<_, u32>(bytes).unwrap();
+        assert_eq!(bytes_of(&cell), ::bytemuck::bytes_of(cell.get()));
+        drop(cell);
+
+        let cell = open_named_with_policy::<[u16; 2], _>(&path, SizePolicy::Strict).unwrap();
+        assert_eq!(*cell.get(), [0x0102, 0x0304]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,70 @@
+/// Everything that can go wrong creating or opening an [`MmapCell`](crate::MmapCell).
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum MmapCellError {
+    #[error("i/o error while mapping file")]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Layout(#[from] LayoutError),
+
+    #[error(transparent)]
+    Header(#[from] HeaderError),
+
+    #[error("file is locked by another process")]
+    Locked,
+}
+
+// lets callers that only deal in io::Result keep using `?`
+impl From<MmapCellError> for std::io::Error {
+    fn from(e: MmapCellError) -> std::io::Error {
+        match e {
+            MmapCellError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Reasons a mapping can't be used as a `T`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LayoutError {
+    #[error("mapping is {actual} bytes but at least {expected} are required")]
+    TooSmall { expected: usize, actual: usize },
+
+    #[error("mapping is {actual} bytes but exactly {expected} are expected")]
+    TrailingBytes { expected: usize, actual: usize },
+
+    #[error("mapping at {addr:#x} is not aligned to {align} bytes")]
+    Misaligned { align: usize, addr: usize },
+
+    #[error("length {len} is past the capacity of {capacity}")]
+    LengthOutOfBounds { len: usize, capacity: usize },
+
+    #[error("capacity {capacity} is not a supported power of two or doesn't match the file")]
+    BadCapacity { capacity: usize },
+
+    #[error("slots are {found} bytes but {expected} were expected")]
+    SlotSizeMismatch { expected: usize, found: usize },
+}
+
+/// Reasons a file header doesn't describe the `T` it is being opened as.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HeaderError {
+    #[error("file is {actual} bytes, too short to hold a header")]
+    Truncated { actual: usize },
+
+    #[error("bad magic bytes {found:?}, file was not written with a header")]
+    BadMagic { found: [u8; 8] },
+
+    #[error("unsupported header version {found}, expected {expected}")]
+    UnsupportedVersion { expected: u32, found: u32 },
+
+    #[error("file holds a {found} byte type but {expected} bytes were expected")]
+    SizeMismatch { expected: u64, found: u64 },
+
+    #[error("file holds a type aligned to {found} bytes but {expected} was expected")]
+    AlignMismatch { expected: u64, found: u64 },
+
+    #[error("layout fingerprint {found:#x} does not match expected {expected:#x}")]
+    FingerprintMismatch { expected: u64, found: u64 },
+}
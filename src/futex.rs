@@ -0,0 +1,116 @@
+//! Thin wrappers around the shared (not `FUTEX_PRIVATE_FLAG`) futex ops,
+//! which key on the underlying file page rather than the virtual address
+//! so they work across every process that maps the same file.
+
+use std::{
+    ptr,
+    sync::atomic::{self, AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Sleeps as long as `atomic` still holds `expected`, until woken or `timeout` passes.
+///
+/// Spurious wakeups are possible so callers always have to recheck.
+pub(crate) fn wait(atomic: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    // SAFETY: an atomic is always valid and aligned
+    unsafe { wait_on(atomic.as_ptr(), expected, timeout) }
+}
+
+/// [`wait`] on a plain address, the kernel only ever reads it.
+///
+/// # Safety
+/// `addr` must be aligned and valid for reads
+unsafe fn wait_on(addr: *const u32, expected: u32, timeout: Option<Duration>) {
+    let timeout = timeout.map(|t| libc::timespec {
+        tv_sec: t.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+        tv_nsec: t.subsec_nanos().into(),
+    });
+
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            addr,
+            libc::FUTEX_WAIT,
+            expected,
+            timeout
+                .as_ref()
+                .map_or(ptr::null(), |t| t as *const libc::timespec),
+        )
+    };
+}
+
+/// Sleeps for as long as `atomic` holds `expected`.
+///
+/// Returns `false` if `timeout` passed with the value still unchanged.
+pub(crate) fn wait_while(atomic: &AtomicU32, expected: u32, timeout: Option<Duration>) -> bool {
+    // SAFETY: an atomic is always valid and aligned
+    unsafe {
+        wait_while_on(
+            atomic.as_ptr(),
+            || atomic.load(Ordering::Acquire),
+            expected,
+            timeout,
+        )
+    }
+}
+
+/// [`wait_while`] on a `u32` this process can only read, like one in a
+/// read-only mapping that other processes update.
+///
+/// # Safety
+/// `addr` must be aligned and valid for reads for as long as this runs
+pub(crate) unsafe fn wait_while_readonly(
+    addr: *const u32,
+    expected: u32,
+    timeout: Option<Duration>,
+) -> bool {
+    // an atomic load would be fine on read-only memory too, but there is no
+    // AtomicU32 to load through without claiming the memory is writable
+    let load = || {
+        let value = unsafe { addr.read_volatile() };
+        atomic::fence(Ordering::Acquire);
+        value
+    };
+
+    unsafe { wait_while_on(addr, load, expected, timeout) }
+}
+
+/// # Safety
+/// `addr` must be aligned and valid for reads for as long as this runs
+unsafe fn wait_while_on(
+    addr: *const u32,
+    load: impl Fn() -> u32,
+    expected: u32,
+    timeout: Option<Duration>,
+) -> bool {
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    while load() == expected {
+        let left = match deadline {
+            None => None,
+            Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+                left if left.is_zero() => return false,
+                left => Some(left),
+            },
+        };
+
+        unsafe { wait_on(addr, expected, left) };
+    }
+
+    true
+}
+
+/// Wakes up to `count` waiters on `atomic` and returns how many there were.
+pub(crate) fn wake(atomic: &AtomicU32, count: i32) -> usize {
+    let woken = unsafe { libc::syscall(libc::SYS_futex, atomic.as_ptr(), libc::FUTEX_WAKE, count) };
+
+    woken.max(0) as usize
+}
+
+pub(crate) fn wake_one(atomic: &AtomicU32) -> bool {
+    wake(atomic, 1) > 0
+}
+
+pub(crate) fn wake_all(atomic: &AtomicU32) -> usize {
+    wake(atomic, i32::MAX)
+}
@@ -0,0 +1,162 @@
+use crate::error::HeaderError;
+
+/// Magic bytes at the start of every file written with a [`Header`].
+pub const MAGIC: [u8; 8] = *b"MMAPCELL";
+
+/// Version of the header format itself, bumped whenever the on-disk
+/// layout of [`Header`] changes.
+pub const HEADER_VERSION: u32 = 1;
+
+/// Self-describing prefix written in front of `T` by
+/// [`MmapCell::new_named_with_header`](crate::MmapCell::new_named_with_header).
+///
+/// It records enough about `T` to catch a file being opened as the wrong type
+/// or an older layout of the right one. The `fingerprint` is entirely up to
+/// the caller, bump it whenever the layout of `T` changes.
+///
+/// On disk the header is always [`Header::LEN`] little-endian bytes:
+///
+/// | bytes    | field         |
+/// |----------|---------------|
+/// | `0..8`   | magic         |
+/// | `8..12`  | version       |
+/// | `12..16` | reserved      |
+/// | `16..24` | `size_of::<T>()`  |
+/// | `24..32` | `align_of::<T>()` |
+/// | `32..40` | fingerprint   |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub magic: [u8; 8],
+    pub version: u32,
+    pub size: u64,
+    pub align: u64,
+    pub fingerprint: u64,
+}
+
+impl Header {
+    /// Size of the encoded header in bytes.
+    pub const LEN: usize = 40;
+
+    /// The header that describes `T` with the given layout fingerprint.
+    pub fn new<T>(fingerprint: u64) -> Header {
+        Header {
+            magic: MAGIC,
+            version: HEADER_VERSION,
+            size: size_of::<T>() as u64,
+            align: align_of::<T>() as u64,
+            fingerprint,
+        }
+    }
+
+    /// Where `T` starts in a mapping that begins with a header.
+    ///
+    /// The header is padded out so that `T` stays aligned
+    /// as long as the mapping itself is.
+    pub fn data_offset<T>() -> usize {
+        Header::LEN.next_multiple_of(align_of::<T>())
+    }
+
+    /// Decodes a header from the start of `bytes`.
+    pub fn read(bytes: &[u8]) -> Result<Header, HeaderError> {
+        let Some(bytes) = bytes.get(..Header::LEN) else {
+            return Err(HeaderError::Truncated {
+                actual: bytes.len(),
+            });
+        };
+
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+
+        Ok(Header {
+            magic: bytes[0..8].try_into().unwrap(),
+            version: u32_at(8),
+            size: u64_at(16),
+            align: u64_at(24),
+            fingerprint: u64_at(32),
+        })
+    }
+
+    /// Encodes the header into the start of `bytes`.
+    ///
+    /// # Panics
+    /// if `bytes` is shorter than [`Header::LEN`]
+    pub fn write(&self, bytes: &mut [u8]) {
+        let bytes = &mut bytes[..Header::LEN];
+
+        bytes[0..8].copy_from_slice(&self.magic);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].fill(0);
+        bytes[16..24].copy_from_slice(&self.size.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.align.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.fingerprint.to_le_bytes());
+    }
+
+    /// Checks that the header at the start of `bytes` matches `self`.
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), HeaderError> {
+        let found = Header::read(bytes)?;
+
+        if found.magic != self.magic {
+            return Err(HeaderError::BadMagic { found: found.magic });
+        }
+
+        if found.version != self.version {
+            return Err(HeaderError::UnsupportedVersion {
+                expected: self.version,
+                found: found.version,
+            });
+        }
+
+        if found.size != self.size {
+            return Err(HeaderError::SizeMismatch {
+                expected: self.size,
+                found: found.size,
+            });
+        }
+
+        if found.align != self.align {
+            return Err(HeaderError::AlignMismatch {
+                expected: self.align,
+                found: found.align,
+            });
+        }
+
+        if found.fingerprint != self.fingerprint {
+            return Err(HeaderError::FingerprintMismatch {
+                expected: self.fingerprint,
+                found: found.fingerprint,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let header = Header::new::<u64>(0xfeed);
+        let mut bytes = [0u8; Header::LEN];
+
+        header.write(&mut bytes);
+
+        assert_eq!(Header::read(&bytes), Ok(header));
+        assert_eq!(header.verify(&bytes), Ok(()));
+        assert_eq!(
+            Header::new::<u64>(0xbeef).verify(&bytes),
+            Err(HeaderError::FingerprintMismatch {
+                expected: 0xbeef,
+                found: 0xfeed
+            })
+        );
+        assert_eq!(
+            Header::new::<u32>(0xfeed).verify(&bytes),
+            Err(HeaderError::SizeMismatch {
+                expected: 4,
+                found: 8
+            })
+        );
+    }
+}
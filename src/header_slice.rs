@@ -0,0 +1,361 @@
+use memmap2::MmapMut;
+use std::{marker::PhantomData, path::Path};
+
+use crate::{LayoutError, MmapCell, MmapCellError, MmapCellOptions, MmapSafe, SizePolicy};
+
+/// A mapping of a fixed header `H` followed by a variable number of `E` entries,
+/// the way a lot of C formats lay out a file.
+///
+/// How many entries there are is read out of the header by the `count` function
+/// given when opening, and checked against the size of the file. The tail starts
+/// right after the header, padded out to `align_of::<E>()`.
+///
+/// Files are opened through [`MmapCellOptions`] (see
+/// [`open_with_options`](MmapHeaderSlice::open_with_options)), so they can be
+/// locked, start with a [`Header`](crate::Header) and are created atomically.
+///
+/// With [`SizePolicy::AllowTrailing`] the file can hold more entries than the header
+/// counts, and raising the count through [`header_mut`](MmapHeaderSlice::header_mut)
+/// makes them part of [`entries`](MmapHeaderSlice::entries) up to [`capacity`](MmapHeaderSlice::capacity).
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{MmapHeaderSlice, MmapSafe};
+///
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Table {
+///     version: u32,
+///     count: u32,
+/// }
+///
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Entry {
+///     key: u64,
+///     value: u64,
+/// }
+///
+/// let path = "/tmp/mmapcell-header-slice-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+/// let count = |t: &Table| t.count as usize;
+///
+/// let mut table =
+///     MmapHeaderSlice::<Table, Entry>::new_named(path, Table { version: 1, count: 3 }, count)
+///         .unwrap();
+///
+/// let (header, entries) = table.split_mut();
+/// entries[2].key = header.version as u64;
+/// drop(table);
+///
+/// let table = MmapHeaderSlice::<Table, Entry>::open_named(path, count).unwrap();
+/// assert_eq!(table.entries().len(), 3);
+/// assert_eq!(table.entries()[2].key, 1);
+/// # }
+/// ```
+pub struct MmapHeaderSlice<H, E> {
+    cell: MmapCell<H>,
+    count: fn(&H) -> usize,
+    /// padding between the header and the first entry
+    pad: usize,
+    _inner: PhantomData<E>,
+}
+
+impl<H: MmapSafe, E: MmapSafe> MmapHeaderSlice<H, E> {
+    /// Wraps an existing mapping, rejecting it unless it holds exactly
+    /// as many entries as the header counts.
+    pub fn new(m: MmapMut, count: fn(&H) -> usize) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        MmapHeaderSlice::new_with_policy(m, count, SizePolicy::default())
+    }
+
+    /// Wraps an existing mapping, rejecting it if it holds fewer entries than
+    /// the header counts and handling any more according to `policy`.
+    pub fn new_with_policy(
+        m: MmapMut,
+        count: fn(&H) -> usize,
+        policy: SizePolicy,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        // the header has to be there before it can be asked for the count
+        let cell = MmapCell::new_with_policy(m, SizePolicy::ExposeTrailing)?;
+
+        MmapHeaderSlice::from_cell(cell, count, policy, Self::pad(0))
+    }
+
+    /// Creates a new file at `path` that starts with `header` and has room
+    /// for exactly the entries it counts, all zero filled.
+    ///
+    /// Fails if the file already exists, see [`MmapHeaderSlice::open_or_create`].
+    pub fn new_named<P: AsRef<Path>>(
+        path: P,
+        header: H,
+        count: fn(&H) -> usize,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError>
+    where
+        H: 'static,
+    {
+        let options = MmapCell::options()
+            .create_new(true)
+            .initializer(move || header);
+
+        MmapHeaderSlice::open_with_options(options, path, count)
+    }
+
+    /// Opens the file at `path`, or creates it like [`MmapHeaderSlice::new_named`]
+    /// if it doesn't exist yet.
+    ///
+    /// Creation is atomic, so when several processes race to create the file
+    /// exactly one header is written and everybody opens that one.
+    pub fn open_or_create<P: AsRef<Path>>(
+        path: P,
+        header: H,
+        count: fn(&H) -> usize,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError>
+    where
+        H: 'static,
+    {
+        let options = MmapCell::options().create(true).initializer(move || header);
+
+        MmapHeaderSlice::open_with_options(options, path, count)
+    }
+
+    /// Opens an existing file at `path`.
+    pub fn open_named<P: AsRef<Path>>(
+        path: P,
+        count: fn(&H) -> usize,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        MmapHeaderSlice::open_named_with_policy(path, count, SizePolicy::default())
+    }
+
+    /// Opens an existing file at `path`, handling any more entries
+    /// than the header counts according to `policy`.
+    pub fn open_named_with_policy<P: AsRef<Path>>(
+        path: P,
+        count: fn(&H) -> usize,
+        policy: SizePolicy,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        MmapHeaderSlice::open_with_options(MmapCell::options().size_policy(policy), path, count)
+    }
+
+    /// Opens the file at `path` the way `options` says, say with a
+    /// [`lock`](MmapCellOptions::lock) or a [`header`](MmapCellOptions::header).
+    ///
+    /// A file it creates gets room for exactly the entries the
+    /// [`initializer`](MmapCellOptions::initializer) (or a zeroed `H`) counts. The size
+    /// policy applies to the entries rather than `H` and resizing doesn't apply.
+    ///
+    /// A header only records the size and alignment of `H`, nothing checks that the
+    /// entries are `E`. Like with [`MmapVec`](crate::MmapVec), it's up to the fingerprint
+    /// to tell entry types apart, so change it whenever `E` changes.
+    pub fn open_with_options<P: AsRef<Path>>(
+        options: MmapCellOptions<H>,
+        path: P,
+        count: fn(&H) -> usize,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        let policy = options.get_size_policy();
+        let pad = Self::pad(options.data_offset());
+
+        let cell = options
+            .resize(false)
+            .size_policy(SizePolicy::ExposeTrailing)
+            .trailing_entries(count, pad, size_of::<E>())
+            .open(path)?;
+
+        MmapHeaderSlice::from_cell(cell, count, policy, pad)
+    }
+
+    fn from_cell(
+        cell: MmapCell<H>,
+        count: fn(&H) -> usize,
+        policy: SizePolicy,
+        pad: usize,
+    ) -> Result<MmapHeaderSlice<H, E>, MmapCellError> {
+        const {
+            assert!(
+                size_of::<E>() != 0,
+                "MmapHeaderSlice can't hold zero sized entries"
+            )
+        };
+
+        let slice = MmapHeaderSlice {
+            cell,
+            count,
+            pad,
+            _inner: PhantomData,
+        };
+
+        let addr = slice.entries_ptr() as usize;
+        if !addr.is_multiple_of(align_of::<E>()) {
+            return Err(LayoutError::Misaligned {
+                align: align_of::<E>(),
+                addr,
+            }
+            .into());
+        }
+
+        let len = (count)(slice.header());
+        let expected = len
+            .checked_mul(size_of::<E>())
+            .and_then(|tail| tail.checked_add(size_of::<H>() + pad))
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::OutOfMemory, "entry count overflow")
+            })?;
+
+        policy.check(expected, size_of::<H>() + slice.cell.trailing)?;
+
+        Ok(slice)
+    }
+
+    /// padding after a header that starts `offset` bytes into the mapping, so the
+    /// entries are aligned relative to the mapping (and so the file) like the header is
+    fn pad(offset: usize) -> usize {
+        let end = offset + size_of::<H>();
+        end.next_multiple_of(align_of::<E>()) - end
+    }
+}
+
+impl<H, E> MmapHeaderSlice<H, E> {
+    /// Where the entries start, for types that share them with other processes
+    /// and can't go through `&mut [E]`.
+    pub(crate) fn entries_ptr(&self) -> *mut E {
+        // SAFETY: the padding is part of the trailing bytes, checked on construction
+        unsafe { self.cell.trailing_ptr().add(self.pad).cast::<E>() }
+    }
+
+    /// How many entries the header currently counts.
+    ///
+    /// The count is checked against the file on open, but another process can
+    /// change the header afterwards, so it's clamped to
+    /// [`capacity`](MmapHeaderSlice::capacity).
+    pub fn len(&self) -> usize {
+        (self.count)(self.header()).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many entries fit in the mapping.
+    pub fn capacity(&self) -> usize {
+        self.cell.trailing.saturating_sub(self.pad) / size_of::<E>()
+    }
+
+    pub fn header(&self) -> &H {
+        self.cell.get()
+    }
+
+    pub fn header_mut(&mut self) -> &mut H {
+        self.cell.get_mut()
+    }
+
+    /// The entries the header currently counts.
+    pub fn entries(&self) -> &[E] {
+        self.split().1
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [E] {
+        self.split_mut().1
+    }
+
+    /// The header and the entries it counts, borrowed together.
+    pub fn split(&self) -> (&H, &[E]) {
+        let len = self.len();
+
+        // SAFETY: size and alignment were checked on construction and
+        // the constructors only accept MmapSafe types
+        let entries = unsafe { std::slice::from_raw_parts(self.entries_ptr(), len) };
+
+        (self.header(), entries)
+    }
+
+    /// The header and the entries it counts, mutably borrowed together.
+    ///
+    /// The entries are sized by the count when this is called,
+    /// changing the header afterwards doesn't affect them.
+    pub fn split_mut(&mut self) -> (&mut H, &mut [E]) {
+        let len = self.len();
+        let entries = self.entries_ptr();
+
+        // SAFETY: the header and the entries never overlap
+        unsafe {
+            (
+                self.cell.get_mut_detached(),
+                std::slice::from_raw_parts_mut(entries, len),
+            )
+        }
+    }
+
+    /// Writes dirty pages back to the file and waits for it to finish.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.cell.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Counted {
+        count: u64,
+    }
+
+    // SAFETY: a single u64
+    unsafe impl MmapSafe for Counted {}
+
+    #[test]
+    fn count_checked_against_file() {
+        let path = std::env::temp_dir().join("mmapcell-header-slice-test.bin");
+        let _ = std::fs::remove_file(&path);
+        let count = |h: &Counted| h.count as usize;
+
+        let slice =
+            MmapHeaderSlice::<Counted, u32>::new_named(&path, Counted { count: 4 }, count).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 8 + 4 * 4);
+        drop(slice);
+
+        // new_named never overwrites an existing file
+        assert!(matches!(
+            MmapHeaderSlice::<Counted, u32>::new_named(&path, Counted { count: 1 }, count),
+            Err(MmapCellError::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+        ));
+
+        // a header counting more entries than the file holds is rejected
+        let mut raw = std::fs::read(&path).unwrap();
+        raw[0..8].copy_from_slice(&5u64.to_ne_bytes());
+        std::fs::write(&path, &raw).unwrap();
+
+        assert!(matches!(
+            MmapHeaderSlice::<Counted, u32>::open_named(&path, count),
+            Err(MmapCellError::Layout(LayoutError::TooSmall {
+                expected: 28,
+                actual: 24
+            }))
+        ));
+
+        // while fewer is only fine if trailing entries are allowed
+        raw[0..8].copy_from_slice(&2u64.to_ne_bytes());
+        std::fs::write(&path, &raw).unwrap();
+
+        assert!(MmapHeaderSlice::<Counted, u32>::open_named(&path, count).is_err());
+
+        let mut slice = MmapHeaderSlice::<Counted, u32>::open_named_with_policy(
+            &path,
+            count,
+            SizePolicy::AllowTrailing,
+        )
+        .unwrap();
+        assert_eq!((slice.len(), slice.capacity()), (2, 4));
+
+        slice.header_mut().count = 4;
+        slice.entries_mut()[3] = 9;
+        assert_eq!(slice.entries(), &[0, 0, 0, 9]);
+
+        // a count raised past what's mapped is clamped rather than trusted
+        slice.header_mut().count = 100;
+        assert_eq!(slice.len(), 4);
+        assert_eq!(slice.entries().len(), 4);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
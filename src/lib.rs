@@ -1,149 +1,792 @@
-#![doc = include_str!("../README.md")]
+#![cfg_attr(feature = "derive", doc = include_str!("../README.md"))]
 
-use memmap2::{MmapMut, MmapOptions};
-use std::{marker::PhantomData, path::Path};
+use lock::FileLock;
+use memmap2::MmapMut;
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::atomic::AtomicU32,
+    time::Duration,
+};
+
+// lets #[derive(MmapSafe)] refer to ::mmapcell from inside this crate too
+extern crate self as mmapcell;
+
+mod broadcast;
+mod error;
+mod futex;
+mod header;
+mod header_slice;
+mod lock;
+mod log;
+mod options;
+mod queue;
+mod readonly;
+mod ring;
+mod safe;
+mod slice;
+mod vec;
+
+pub mod sync;
+
+#[cfg(feature = "bytemuck")]
+pub mod bytemuck;
+#[cfg(feature = "zerocopy")]
+pub mod zerocopy;
+
+pub use broadcast::{BroadcastReader, Lapped, MmapBroadcast};
+pub use error::{HeaderError, LayoutError, MmapCellError};
+pub use header::{Header, HEADER_VERSION, MAGIC};
+pub use header_slice::MmapHeaderSlice;
+pub use lock::LockMode;
+pub use log::{LogIter, MmapLog, MmapLogReader};
+pub use options::{FlushOnDrop, MmapCellOptions};
+pub use queue::MmapQueue;
+pub use readonly::MmapCellRef;
+pub use ring::MmapRing;
+pub use safe::{MmapPod, MmapSafe};
+pub use slice::MmapSlice;
+pub use vec::MmapVec;
+
+#[cfg(feature = "derive")]
+pub use mmapcell_derive::{MmapPod, MmapSafe};
+
+/// What to do with a mapping that is larger than `size_of::<T>()`.
+///
+/// Mappings that are smaller than `size_of::<T>()` are always rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizePolicy {
+    /// the mapping must be exactly `size_of::<T>()` bytes
+    #[default]
+    Strict,
+    /// bytes past `size_of::<T>()` are allowed but ignored
+    AllowTrailing,
+    /// bytes past `size_of::<T>()` are allowed and exposed through
+    /// [`MmapCell::trailing`] and [`MmapCell::trailing_mut`]
+    ExposeTrailing,
+}
+
+impl SizePolicy {
+    /// checks a mapping of `actual` bytes against a type of `expected` bytes
+    /// and returns how many trailing bytes should be exposed
+    fn check(self, expected: usize, actual: usize) -> Result<usize, LayoutError> {
+        if actual < expected {
+            return Err(LayoutError::TooSmall { expected, actual });
+        }
+
+        match self {
+            SizePolicy::Strict if actual != expected => {
+                Err(LayoutError::TrailingBytes { expected, actual })
+            }
+            SizePolicy::Strict | SizePolicy::AllowTrailing => Ok(0),
+            SizePolicy::ExposeTrailing => Ok(actual - expected),
+        }
+    }
+}
 
 /// A wrapper wrapper for a memory-mapped file with data of type `T`.
 ///
 /// # Safety
 ///
-/// `T` must have a consistent memory layout to ensure that the data is casted correctly.
-///
-/// Use `#[repr(transparent)]` if `T` is a newtype wrapper around a single field otherwise `#[repr(C)]`.
+/// `T` must have a consistent memory layout and be valid for whatever bytes are in
+/// the file, which is exactly what [`MmapSafe`] promises. `#[derive(MmapSafe)]` checks
+/// that at compile time and unlocks the safe constructors. Types that can't be
+/// `MmapSafe` have to go through [`MmapCell::new_unchecked`].
 ///
 /// # Example
 /// ```rust
-/// use mmapcell::MmapCell;
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{MmapCell, MmapSafe};
 ///
+/// #[derive(MmapSafe)]
 /// #[repr(C)]
 /// struct MyStruct {
-///    thing1: i32,
+///    thing1: i64,
 ///    thing2: f64,
 /// }
 ///
-/// let mut cell = unsafe {
-///     MmapCell::<MyStruct>::new_named("/tmp/mystruct-mmap-test.bin")
-/// }.unwrap();
+/// let mut cell = MmapCell::<MyStruct>::new_named("/tmp/mystruct-mmap-test.bin").unwrap();
 ///
 /// let mmap_backed_mystruct = cell.get_mut();
 ///
 /// mmap_backed_mystruct.thing1 = 3;
+/// # }
 /// ```
-#[repr(transparent)]
 pub struct MmapCell<T> {
     raw: MmapMut,
+    // dropped after raw so the file stays locked until it's unmapped
+    _lock: Option<FileLock>,
+    offset: usize,
+    trailing: usize,
+    flush: FlushOnDrop,
     _inner: PhantomData<T>,
 }
 
 impl<T> Drop for MmapCell<T> {
     fn drop(&mut self) {
         // this probably happens anyways but just in case
-        let _ = self.raw.flush();
+        let _ = match self.flush {
+            FlushOnDrop::Never => Ok(()),
+            FlushOnDrop::Async => self.raw.flush_async(),
+            FlushOnDrop::Sync => self.raw.flush(),
+        };
+    }
+}
+
+impl<T> Deref for MmapCell<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.get()
+    }
+}
+
+impl<T> DerefMut for MmapCell<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.get_mut()
     }
 }
 
-// WARN:
-// i'm not sure i want to leave these in because
-// it isn't super clear that these are VERY unsafe to call
-//
-//impl<T> TryFrom<Mmap> for MmapCell<T> {
-//    type Error = std::io::Error;
-//
-//    fn try_from(m: Mmap) -> Result<MmapCell<T>, std::io::Error> {
-//        Ok(unsafe { MmapCell::new(m.make_mut()?) })
-//    }
-//}
-//
-//impl<T> From<MmapMut> for MmapCell<T> {
-//    fn from(m: MmapMut) -> MmapCell<T> {
-//        unsafe { MmapCell::new(m) }
-//    }
-//}
+// only for MmapSafe types, anything else still has to
+// go through the (very unsafe) MmapCell::new_unchecked
+impl<T: MmapSafe> TryFrom<MmapMut> for MmapCell<T> {
+    type Error = MmapCellError;
+
+    fn try_from(m: MmapMut) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::new(m)
+    }
+}
+
+/// checks that `bytes` can hold a T at `offset` and returns how many
+/// trailing bytes to expose
+///
+/// get and get_mut cast the pointer at `offset` straight to T so this is
+/// the one place size and alignment have to be checked
+fn check_layout<T>(bytes: &[u8], offset: usize, policy: SizePolicy) -> Result<usize, LayoutError> {
+    let trailing = policy.check(offset + size_of::<T>(), bytes.len())?;
+
+    let addr = bytes.as_ptr() as usize + offset;
+    if !addr.is_multiple_of(align_of::<T>()) {
+        return Err(LayoutError::Misaligned {
+            align: align_of::<T>(),
+            addr,
+        });
+    }
+
+    Ok(trailing)
+}
+
+impl<T: MmapSafe> MmapCell<T> {
+    /// Wraps an existing mapping, rejecting it unless it is exactly
+    /// `size_of::<T>()` bytes and aligned for `T`.
+    pub fn new(m: MmapMut) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::new_with_policy(m, SizePolicy::default())
+    }
+
+    /// Wraps an existing mapping, rejecting it if it is smaller than
+    /// `size_of::<T>()` or not aligned for `T` and handling any extra
+    /// bytes according to `policy`.
+    pub fn new_with_policy(m: MmapMut, policy: SizePolicy) -> Result<MmapCell<T>, MmapCellError> {
+        // SAFETY: T is MmapSafe so whatever bytes are mapped are a valid T
+        unsafe { MmapCell::from_parts(m, 0, policy) }
+    }
+
+    pub fn new_anon() -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().open_anon()
+    }
+
+    pub fn new_named<P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().create(true).resize(true).open(path)
+    }
+
+    /// Like [`MmapCell::new_named`] but writes `init()` into the file if (and only if)
+    /// this call created it, instead of leaving it zeroed.
+    ///
+    /// Creation is atomic, see [`MmapCellOptions::initializer`].
+    pub fn new_named_with<P, F>(path: P, init: F) -> Result<MmapCell<T>, MmapCellError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce() -> T + 'static,
+    {
+        MmapCell::options()
+            .create(true)
+            .resize(true)
+            .initializer(init)
+            .open(path)
+    }
+
+    /// Opens the file if it exists, otherwise creates it with `init()` as its contents.
+    ///
+    /// Unlike [`MmapCell::new_named_with`] an existing file is never resized, so one of
+    /// the wrong size is rejected. Creation is atomic, see [`MmapCellOptions::initializer`].
+    pub fn open_or_create<P, F>(path: P, init: F) -> Result<MmapCell<T>, MmapCellError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce() -> T + 'static,
+    {
+        MmapCell::options()
+            .create(true)
+            .initializer(init)
+            .open(path)
+    }
+
+    /// Like [`MmapCell::new_named`] but holds a `flock` on the file
+    /// until the cell is dropped.
+    ///
+    /// Fails with [`MmapCellError::Locked`] if a try-lock times out.
+    pub fn new_named_locked<P: AsRef<Path>>(
+        path: P,
+        mode: LockMode,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options()
+            .create(true)
+            .resize(true)
+            .lock(mode)
+            .open(path)
+    }
+
+    /// Opens an existing file, rejecting it unless it is exactly
+    /// `size_of::<T>()` bytes.
+    pub fn open_named<P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().open(path)
+    }
+
+    /// Opens an existing file, rejecting it if it is smaller than
+    /// `size_of::<T>()` and handling any extra bytes according to `policy`.
+    pub fn open_named_with_policy<P: AsRef<Path>>(
+        path: P,
+        policy: SizePolicy,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().size_policy(policy).open(path)
+    }
+
+    /// Like [`MmapCell::open_named`] but holds a `flock` on the file
+    /// until the cell is dropped.
+    ///
+    /// Fails with [`MmapCellError::Locked`] if a try-lock times out.
+    pub fn open_named_locked<P: AsRef<Path>>(
+        path: P,
+        mode: LockMode,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().lock(mode).open(path)
+    }
+
+    /// Creates (or opens) a file that starts with a [`Header`] describing `T`.
+    ///
+    /// A new file gets a freshly written header, an existing one has its header
+    /// checked against `T` and `fingerprint` and is rejected on any mismatch.
+    ///
+    /// Only a file this call creates gets a header, and it's created atomically (see
+    /// [`MmapCellOptions::initializer`]) so racing callers never see it without one.
+    /// An existing file is never written to, even an empty one.
+    pub fn new_named_with_header<P: AsRef<Path>>(
+        path: P,
+        fingerprint: u64,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options()
+            .create(true)
+            .header(fingerprint)
+            .open(path)
+    }
+
+    /// Opens an existing file written by [`MmapCell::new_named_with_header`],
+    /// rejecting it unless its header matches `T` and `fingerprint`.
+    pub fn open_named_with_header<P: AsRef<Path>>(
+        path: P,
+        fingerprint: u64,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        MmapCell::options().header(fingerprint).open(path)
+    }
+
+    /// The bytes of the mapped `T`, writable.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: MmapSafe types have no padding and are valid for any bytes
+        unsafe { self.bytes_mut_unchecked() }
+    }
+
+    /// Reinterprets the cell as another [`MmapSafe`] type of the same size,
+    /// for example a `[u8; N]` byte view.
+    ///
+    /// Fails to compile if `U` and `T` differ in size and returns an error if the
+    /// mapping isn't aligned for `U`, dropping the cell without flushing it.
+    pub fn cast<U: MmapSafe>(self) -> Result<MmapCell<U>, MmapCellError> {
+        // SAFETY: the bytes of any MmapSafe type are a valid MmapSafe type
+        unsafe { self.cast_unchecked() }
+    }
+}
+
+impl<T: MmapPod> MmapCell<T> {
+    /// The bytes of the mapped `T`.
+    ///
+    /// Only for [`MmapPod`] types, the bytes of an atomic could
+    /// change under the returned slice.
+    ///
+    /// ```compile_fail
+    /// # use mmapcell::MmapCell;
+    /// let cell = MmapCell::<std::sync::atomic::AtomicU32>::new_anon().unwrap();
+    /// let bytes = cell.as_bytes();
+    /// ```
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: MmapSafe types have no padding and
+        // MmapPod ones can't change behind a shared reference
+        unsafe { self.bytes_unchecked() }
+    }
+}
 
 impl<T> MmapCell<T> {
+    /// Shorthand for [`MmapCellOptions::new`].
+    pub fn options() -> MmapCellOptions<T> {
+        MmapCellOptions::new()
+    }
+
+    /// Wraps an existing mapping for a `T` that isn't [`MmapSafe`].
+    ///
+    /// Size and alignment are still checked exactly like [`MmapCell::new_with_policy`].
+    ///
     /// # Safety
     /// the backing mmap pointer must point to valid
     /// memory for type T [T likely has to be repr(C)]
-    pub unsafe fn new(m: MmapMut) -> MmapCell<T> {
-        // check that size of m matches
-        // size of inner type
-        MmapCell {
-            raw: m,
-            _inner: PhantomData,
-        }
+    pub unsafe fn new_unchecked(
+        m: MmapMut,
+        policy: SizePolicy,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        unsafe { MmapCell::from_parts(m, 0, policy) }
     }
 
-    pub fn new_anon() -> Result<MmapCell<T>, std::io::Error> {
-        Ok(unsafe { MmapCell::new(MmapOptions::new().len(size_of::<T>()).map_anon()?) })
+    /// Reinterprets the cell as a `U` of the same size.
+    ///
+    /// Fails to compile if `U` and `T` differ in size. If the mapping isn't aligned
+    /// for `U` the cell is dropped without being flushed, whatever its [`FlushOnDrop`].
+    ///
+    /// # Safety
+    /// the bytes of the current T must be a valid U
+    pub(crate) unsafe fn cast_unchecked<U>(self) -> Result<MmapCell<U>, MmapCellError> {
+        const {
+            assert!(
+                size_of::<T>() == size_of::<U>(),
+                "cast between types of different sizes"
+            )
+        };
+
+        let this = std::mem::ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped so the mapping and lock are moved out exactly once
+        let raw = unsafe { std::ptr::read(&this.raw) };
+        let lock = unsafe { std::ptr::read(&this._lock) };
+
+        let mut cast =
+            unsafe { MmapCell::<U>::from_parts(raw, this.offset, SizePolicy::AllowTrailing)? };
+        cast._lock = lock;
+        cast.trailing = this.trailing;
+        cast.flush = this.flush;
+
+        Ok(cast)
     }
 
+    /// The bytes that make up the mapped `T`.
+    ///
     /// # Safety
-    /// the backing mmap pointer must point to valid
-    /// memory for type T [T likely has to be repr(C)]
-    pub unsafe fn new_named<P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, std::io::Error> {
-        let file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(path)?;
+    /// T must not contain any padding (uninitialized) bytes
+    pub(crate) unsafe fn bytes_unchecked(&self) -> &[u8] {
+        &self.raw[self.offset..self.offset + size_of::<T>()]
+    }
 
-        file.set_len(size_of::<T>() as u64)?;
+    /// The bytes that make up the mapped `T`.
+    ///
+    /// # Safety
+    /// T must not contain any padding (uninitialized) bytes
+    /// and must be valid for any bytes written to it
+    pub(crate) unsafe fn bytes_mut_unchecked(&mut self) -> &mut [u8] {
+        &mut self.raw[self.offset..self.offset + size_of::<T>()]
+    }
 
-        let m = unsafe { MmapMut::map_mut(&file)? };
-        Ok(unsafe { MmapCell::new(m) })
+    /// # Safety
+    /// the mmap must hold a valid T at `offset`
+    unsafe fn from_parts(
+        m: MmapMut,
+        offset: usize,
+        policy: SizePolicy,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        let trailing = check_layout::<T>(&m, offset, policy)?;
+
+        Ok(MmapCell {
+            raw: m,
+            _lock: None,
+            offset,
+            trailing,
+            flush: FlushOnDrop::default(),
+            _inner: PhantomData,
+        })
     }
 
+    /// Overwrites whatever is mapped with `value` without dropping it.
+    ///
     /// # Safety
-    /// the backing mmap pointer must point to valid
-    /// memory for type T [T likely has to be repr(C)]
-    pub unsafe fn open_named<P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, std::io::Error> {
-        let file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(false)
-            .truncate(false)
-            .open(path)?;
+    /// the current contents don't need to be dropped
+    unsafe fn init(&mut self, value: T) {
+        unsafe {
+            self.raw
+                .as_mut_ptr()
+                .add(self.offset)
+                .cast::<T>()
+                .write(value)
+        }
+    }
 
-        let m = unsafe { MmapMut::map_mut(&file)? };
+    /// The mapped `T`, borrowed for as long as the cell.
+    ///
+    /// ```compile_fail
+    /// use mmapcell::MmapCell;
+    ///
+    /// let detached = {
+    ///     let cell = MmapCell::<u64>::new_anon().unwrap();
+    ///     cell.get()
+    /// };
+    /// ```
+    pub fn get(&self) -> &T {
+        unsafe { self.get_detached() }
+    }
 
-        Ok(unsafe { MmapCell::new(m) })
+    /// The mapped `T`, mutably borrowed for as long as the cell.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { self.get_mut_detached() }
     }
 
-    pub fn get<'a>(&self) -> &'a T {
+    /// Like [`MmapCell::get`] but the reference isn't tied to the cell.
+    ///
+    /// # Safety
+    /// the reference must not outlive the cell (which unmaps the memory
+    /// on drop) and must not overlap with any `&mut T` to the same data
+    pub unsafe fn get_detached<'a>(&self) -> &'a T {
         unsafe {
             self.raw
                 .as_ptr()
+                .add(self.offset)
                 .cast::<T>()
                 .as_ref()
                 .expect("not null pointer")
         }
     }
 
-    pub fn get_mut<'a>(&mut self) -> &'a mut T {
+    /// Like [`MmapCell::get_mut`] but the reference isn't tied to the cell.
+    ///
+    /// # Safety
+    /// the reference must not outlive the cell (which unmaps the memory
+    /// on drop) and must not overlap with any other reference to the same data
+    pub unsafe fn get_mut_detached<'a>(&mut self) -> &'a mut T {
         unsafe {
             self.raw
                 .as_mut_ptr()
+                .add(self.offset)
                 .cast::<T>()
                 .as_mut()
                 .expect("non null pointer")
         }
     }
+
+    /// Writes dirty pages back to the file and waits for it to finish.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.raw.flush()
+    }
+
+    /// The bytes past `size_of::<T>()`.
+    ///
+    /// Always empty unless the cell was created with [`SizePolicy::ExposeTrailing`].
+    pub fn trailing(&self) -> &[u8] {
+        let start = self.offset + size_of::<T>();
+        &self.raw[start..start + self.trailing]
+    }
+
+    /// The bytes past `size_of::<T>()`.
+    ///
+    /// Always empty unless the cell was created with [`SizePolicy::ExposeTrailing`].
+    pub fn trailing_mut(&mut self) -> &mut [u8] {
+        let start = self.offset + size_of::<T>();
+        &mut self.raw[start..start + self.trailing]
+    }
+
+    /// Where the trailing bytes start, for the containers that lay their
+    /// own data out past the `T` and only ever touch it through pointers.
+    pub(crate) fn trailing_ptr(&self) -> *mut u8 {
+        // SAFETY: the mapping was checked to hold a T at offset on construction
+        unsafe {
+            self.raw
+                .as_ptr()
+                .add(self.offset + size_of::<T>())
+                .cast_mut()
+        }
+    }
+
+    /// Sleeps for as long as the `AtomicU32` picked out by `field` holds `expected`,
+    /// or until `timeout` passes.
+    ///
+    /// The wait is keyed on the file rather than this mapping, so it's woken by
+    /// [`notify_one`](MmapCell::notify_one) and [`notify_all`](MmapCell::notify_all)
+    /// from any process that maps the same file. Returns `false` if it timed out
+    /// with the value still unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// # #[cfg(feature = "derive")] {
+    /// use mmapcell::{MmapCell, MmapSafe};
+    /// use std::sync::atomic::{AtomicU32, Ordering};
+    ///
+    /// #[derive(MmapSafe)]
+    /// #[repr(C)]
+    /// struct Mailbox {
+    ///     ready: AtomicU32,
+    ///     value: AtomicU32,
+    /// }
+    ///
+    /// let path = "/tmp/mmapcell-wait-while-doc-test.bin";
+    /// let consumer = MmapCell::<Mailbox>::new_named(path).unwrap();
+    /// let producer = MmapCell::<Mailbox>::open_named(path).unwrap();
+    ///
+    /// producer.ready.store(0, Ordering::Release);
+    ///
+    /// std::thread::spawn(move || {
+    ///     producer.value.store(42, Ordering::Relaxed);
+    ///     producer.ready.store(1, Ordering::Release);
+    ///     producer.notify_all(|m| &m.ready);
+    /// });
+    ///
+    /// consumer.wait_while(|m| &m.ready, 0, None);
+    /// assert_eq!(consumer.value.load(Ordering::Relaxed), 42);
+    /// # }
+    /// ```
+    pub fn wait_while<F>(&self, field: F, expected: u32, timeout: Option<Duration>) -> bool
+    where
+        F: FnOnce(&T) -> &AtomicU32,
+    {
+        futex::wait_while(field(self.get()), expected, timeout)
+    }
+
+    /// Wakes one process (or thread) waiting on the `AtomicU32` picked out by `field`.
+    ///
+    /// Returns whether anybody was actually waiting.
+    pub fn notify_one<F>(&self, field: F) -> bool
+    where
+        F: FnOnce(&T) -> &AtomicU32,
+    {
+        futex::wake_one(field(self.get()))
+    }
+
+    /// Wakes everybody waiting on the `AtomicU32` picked out by `field`
+    /// and returns how many there were.
+    pub fn notify_all<F>(&self, field: F) -> usize
+    where
+        F: FnOnce(&T) -> &AtomicU32,
+    {
+        futex::wake_all(field(self.get()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[repr(C)]
     struct TestStruct {
         thing1: i32,
     }
 
+    // SAFETY: a single i32, implemented by hand so the
+    // tests don't depend on the derive feature
+    unsafe impl MmapSafe for TestStruct {}
+
     #[test]
     fn anon_mmapcell() {
         let mut anon_cell = MmapCell::<TestStruct>::new_anon().unwrap();
         anon_cell.get_mut().thing1 = 3;
 
         assert!(anon_cell.get().thing1 == 3);
+
+        anon_cell.thing1 += 1;
+        assert!(anon_cell.thing1 == 4);
+
+        let bytes = anon_cell.cast::<[u8; 4]>().unwrap();
+        assert_eq!(*bytes.get(), 4i32.to_ne_bytes());
+        assert_eq!(bytes.as_bytes(), &4i32.to_ne_bytes());
+    }
+
+    #[test]
+    fn size_policy() {
+        let path = std::env::temp_dir().join("mmapcell-size-policy-test.bin");
+        std::fs::write(&path, [7u8; 6]).unwrap();
+
+        let err = MmapCell::<[u8; 8]>::open_named(&path).err().unwrap();
+        assert!(matches!(
+            err,
+            MmapCellError::Layout(LayoutError::TooSmall {
+                expected: 8,
+                actual: 6
+            })
+        ));
+
+        assert!(MmapCell::<[u8; 4]>::open_named(&path).is_err());
+
+        let cell =
+            MmapCell::<[u8; 4]>::open_named_with_policy(&path, SizePolicy::AllowTrailing).unwrap();
+        assert!(cell.trailing().is_empty());
+
+        let cell =
+            MmapCell::<[u8; 4]>::open_named_with_policy(&path, SizePolicy::ExposeTrailing).unwrap();
+        assert_eq!(cell.trailing(), &[7, 7]);
+
+        // an empty file is opened as is rather than grown to fit
+        std::fs::write(&path, []).unwrap();
+        assert!(matches!(
+            MmapCell::<u64>::open_named(&path),
+            Err(MmapCellError::Layout(LayoutError::TooSmall {
+                expected: 8,
+                actual: 0
+            }))
+        ));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn io_error_source() {
+        let path = std::env::temp_dir().join("mmapcell-does-not-exist.bin");
+
+        let err = MmapCell::<u64>::open_named(&path).err().unwrap();
+
+        assert!(matches!(err, MmapCellError::Io(_)));
+        assert!(std::error::Error::source(&err)
+            .and_then(|e| e.downcast_ref::<std::io::Error>())
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn header_mismatch() {
+        let path = std::env::temp_dir().join("mmapcell-header-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut cell = MmapCell::<u64>::new_named_with_header(&path, 1).unwrap();
+        *cell.get_mut() = 42;
+        drop(cell);
+
+        let cell = MmapCell::<u64>::open_named_with_header(&path, 1).unwrap();
+        assert_eq!(*cell.get(), 42);
+        drop(cell);
+
+        let err = MmapCell::<u64>::open_named_with_header(&path, 2)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            MmapCellError::Header(HeaderError::FingerprintMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+
+        let err = MmapCell::<u32>::open_named_with_header(&path, 1)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            MmapCellError::Header(HeaderError::SizeMismatch {
+                expected: 4,
+                found: 8
+            })
+        ));
+
+        // an empty file didn't come from here and doesn't get a header
+        std::fs::write(&path, []).unwrap();
+        assert!(matches!(
+            MmapCell::<u64>::new_named_with_header(&path, 1),
+            Err(MmapCellError::Layout(LayoutError::TooSmall { .. }))
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        // racing creators all end up with the one file and a valid header
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || MmapCell::<u64>::new_named_with_header(&path, 1).is_ok())
+            })
+            .collect();
+
+        assert!(threads.into_iter().all(|t| t.join().unwrap()));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn file_lock() {
+        use std::time::Duration;
+
+        let path = std::env::temp_dir().join("mmapcell-lock-test.bin");
+
+        let writer = MmapCell::<u64>::new_named_locked(&path, LockMode::Exclusive).unwrap();
+
+        // flock is per open file so this conflicts even from the same process
+        let err = MmapCell::<u64>::open_named_locked(&path, LockMode::TryShared(Duration::ZERO))
+            .err()
+            .unwrap();
+        assert!(matches!(err, MmapCellError::Locked));
+
+        drop(writer);
+
+        let reader1 =
+            MmapCell::<u64>::open_named_locked(&path, LockMode::TryShared(Duration::ZERO)).unwrap();
+        let reader2 =
+            MmapCell::<u64>::open_named_locked(&path, LockMode::TryShared(Duration::ZERO)).unwrap();
+
+        let err = MmapCell::<u64>::open_named_locked(
+            &path,
+            LockMode::TryExclusive(Duration::from_millis(20)),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(err, MmapCellError::Locked));
+
+        drop((reader1, reader2));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn misaligned_mapping() {
+        let path = std::env::temp_dir().join("mmapcell-misaligned-test.bin");
+        std::fs::write(&path, [0u8; 9]).unwrap();
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let m = unsafe { memmap2::MmapOptions::new().offset(1).len(8).map_mut(&file) }.unwrap();
+
+        let err = MmapCell::<u64>::new(m).err().unwrap();
+        assert!(matches!(
+            err,
+            MmapCellError::Layout(LayoutError::Misaligned { align: 8, .. })
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn wait_and_notify() {
+        let path = std::env::temp_dir().join("mmapcell-futex-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let waiter = MmapCell::<AtomicU32>::new_named(&path).unwrap();
+        let notifier = MmapCell::<AtomicU32>::open_named(&path).unwrap();
+
+        assert!(!waiter.wait_while(|a| a, 0, Some(Duration::from_millis(10))));
+
+        let handle = std::thread::spawn(move || waiter.wait_while(|a| a, 0, None));
+
+        std::thread::sleep(Duration::from_millis(20));
+        notifier.store(1, std::sync::atomic::Ordering::Release);
+        notifier.notify_all(|a| a);
+
+        assert!(handle.join().unwrap());
+
+        let _ = std::fs::remove_file(&path);
     }
 }
@@ -0,0 +1,101 @@
+use std::{
+    fs::File,
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use crate::MmapCellError;
+
+/// How long to sleep between attempts while waiting on a try-lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Advisory `flock` taken on the backing file for the lifetime of a cell.
+///
+/// These only coordinate processes that also lock the file,
+/// anything mapping it without a lock is not kept out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// a single writer, blocks until every other lock is released
+    Exclusive,
+    /// any number of readers, blocks while an exclusive lock is held
+    Shared,
+    /// like [`LockMode::Exclusive`] but gives up with
+    /// [`MmapCellError::Locked`] once the timeout passes
+    TryExclusive(Duration),
+    /// like [`LockMode::Shared`] but gives up with
+    /// [`MmapCellError::Locked`] once the timeout passes
+    TryShared(Duration),
+}
+
+impl LockMode {
+    fn operation(self) -> libc::c_int {
+        match self {
+            LockMode::Exclusive | LockMode::TryExclusive(_) => libc::LOCK_EX,
+            LockMode::Shared | LockMode::TryShared(_) => libc::LOCK_SH,
+        }
+    }
+
+    fn timeout(self) -> Option<Duration> {
+        match self {
+            LockMode::Exclusive | LockMode::Shared => None,
+            LockMode::TryExclusive(t) | LockMode::TryShared(t) => Some(t),
+        }
+    }
+}
+
+/// Keeps the file locked until dropped.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    pub(crate) fn acquire(file: &File, mode: LockMode) -> Result<FileLock, MmapCellError> {
+        // the duplicate shares the lock with the original,
+        // which is free to go out of scope once it's mapped
+        let file = file.try_clone()?;
+        let fd = file.as_raw_fd();
+
+        let Some(timeout) = mode.timeout() else {
+            flock(fd, mode.operation())?;
+            return Ok(FileLock { file });
+        };
+
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match flock(fd, mode.operation() | libc::LOCK_NB) {
+                Ok(()) => return Ok(FileLock { file }),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(MmapCellError::Locked);
+            }
+
+            std::thread::sleep(RETRY_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // closing the file releases it anyways but be explicit about it
+        let _ = flock(self.file.as_raw_fd(), libc::LOCK_UN);
+    }
+}
+
+fn flock(fd: libc::c_int, operation: libc::c_int) -> Result<(), std::io::Error> {
+    loop {
+        if unsafe { libc::flock(fd, operation) } == 0 {
+            return Ok(());
+        }
+
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
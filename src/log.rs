@@ -0,0 +1,590 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    LayoutError, LockMode, MmapCell, MmapCellError, MmapCellOptions, MmapCellRef, MmapPod,
+    MmapSafe, SizePolicy,
+};
+
+/// Layout fingerprint in the [`Header`](crate::Header) of every segment file.
+const SEGMENT_FINGERPRINT: u64 = u64::from_le_bytes(*b"mmaplog1");
+
+/// Every record starts with its length and a checksum of the length and payload.
+const RECORD_HEADER: usize = 8;
+
+#[repr(C)]
+struct SegmentHeader {
+    /// offset of the segment's first record in the log as a whole
+    base: u64,
+}
+
+// SAFETY: a single plain word
+unsafe impl MmapSafe for SegmentHeader {}
+
+// SAFETY: a single plain word, with no interior mutability
+unsafe impl MmapPod for SegmentHeader {}
+
+/// An append-only log of byte records spread over segment files in a directory.
+///
+/// Records are length prefixed and checksummed, and every record has an offset that
+/// stays the same for good. Once a segment doesn't have room for the next record
+/// a new one of `segment_size` bytes is started, named after the offset it starts at.
+///
+/// Opening the log again scans the newest segment for the last record with a
+/// good checksum and carries on appending right after it, so a record that
+/// was only half written when the writer died is simply dropped. Segments
+/// are flushed as the log moves past them, so only the newest one can have
+/// records that never made it to disk.
+///
+/// Only one `MmapLog` can have a directory open at a time, every segment
+/// is locked while it's open and a second one fails with
+/// [`MmapCellError::Locked`]. Any number of [`MmapLogReader`]s can read
+/// it alongside, see [`MmapLog::open_reader`].
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapLog;
+///
+/// let dir = "/tmp/mmapcell-log-doc-test";
+/// let _ = std::fs::remove_dir_all(dir);
+///
+/// let mut log = MmapLog::open(dir, 4096).unwrap();
+///
+/// let first = log.append(b"order 1 filled").unwrap();
+/// let second = log.append(b"order 2 cancelled").unwrap();
+/// log.flush().unwrap();
+/// drop(log);
+///
+/// let log = MmapLog::open(dir, 4096).unwrap();
+/// let mut records = log.iter_from(second).unwrap();
+///
+/// assert_eq!(log.iter().next(), Some((first, &b"order 1 filled"[..])));
+/// assert_eq!(records.next(), Some((second, &b"order 2 cancelled"[..])));
+/// assert_eq!(records.next(), None);
+/// ```
+pub struct MmapLog {
+    dir: PathBuf,
+    segment_size: usize,
+    /// oldest first, only the last one is ever appended to
+    segments: Vec<MmapCell<SegmentHeader>>,
+    /// where the next record goes in the last segment
+    end: usize,
+}
+
+impl MmapLog {
+    /// Opens the log in `dir`, creating the directory and a first segment if needed.
+    ///
+    /// New segments are `segment_size` bytes, existing ones
+    /// keep whatever size they were created with.
+    pub fn open<P: AsRef<Path>>(dir: P, segment_size: usize) -> Result<MmapLog, MmapCellError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut segments = Vec::new();
+
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == "log") {
+                let segment = segment_options().open(&path)?;
+
+                segments.push(segment);
+            }
+        }
+
+        segments.sort_by_key(|s| s.get().base);
+
+        let mut log = MmapLog {
+            dir,
+            segment_size,
+            segments,
+            end: 0,
+        };
+
+        match log.segments.last() {
+            None => log.roll(0)?,
+            Some(last) => {
+                let bytes = last.trailing();
+                while let Some(record) = read_record(bytes, log.end) {
+                    log.end += RECORD_HEADER + record.len();
+                }
+            }
+        }
+
+        Ok(log)
+    }
+
+    /// Appends `record`, starting a new segment first if it doesn't fit
+    /// in the current one, and returns its offset.
+    ///
+    /// Fails with [`LayoutError::LengthOutOfBounds`] if the record fits neither
+    /// in what's left of the current segment nor in a new one of `segment_size` bytes.
+    pub fn append(&mut self, record: &[u8]) -> Result<u64, MmapCellError> {
+        let len = RECORD_HEADER + record.len();
+
+        if record.len() > u32::MAX as usize {
+            return Err(LayoutError::LengthOutOfBounds {
+                len,
+                capacity: RECORD_HEADER + u32::MAX as usize,
+            }
+            .into());
+        }
+
+        let room = self.last().trailing().len();
+
+        if self.end + len > room {
+            // an empty segment can't be rolled past, there'd be two starting at the same offset
+            let capacity = match self.end {
+                0 => room,
+                _ => self.segment_size,
+            };
+
+            if len > capacity {
+                return Err(LayoutError::LengthOutOfBounds { len, capacity }.into());
+            }
+
+            self.roll(self.end_offset())?;
+        }
+
+        let offset = self.end_offset();
+        let at = self.end;
+        let bytes = self.last_mut().trailing_mut();
+
+        // whatever a torn write left behind mustn't look like the record after this one
+        let stop = (at + len + RECORD_HEADER).min(bytes.len());
+        bytes[at + len..stop].fill(0);
+
+        let header = (record.len() as u32).to_le_bytes();
+        let checksum = crc32(crc32(!0, &header), record);
+
+        bytes[at + RECORD_HEADER..at + len].copy_from_slice(record);
+        bytes[at..at + 4].copy_from_slice(&header);
+        bytes[at + 4..at + 8].copy_from_slice(&(!checksum).to_le_bytes());
+
+        self.end += len;
+
+        Ok(offset)
+    }
+
+    /// Offset the next record will be appended at.
+    pub fn end_offset(&self) -> u64 {
+        self.last().get().base + self.end as u64
+    }
+
+    /// Offset of the oldest record still in the log.
+    pub fn start_offset(&self) -> u64 {
+        self.segments[0].get().base
+    }
+
+    /// How many segment files the log is spread over.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Opens the log in `dir` for reading alongside the writer, if there is one.
+    ///
+    /// Segments are mapped read-only and without a lock, see [`MmapLogReader`].
+    pub fn open_reader<P: AsRef<Path>>(dir: P) -> Result<MmapLogReader, MmapCellError> {
+        let mut reader = MmapLogReader {
+            dir: dir.as_ref().to_path_buf(),
+            segments: Vec::new(),
+        };
+
+        reader.refresh()?;
+
+        Ok(reader)
+    }
+
+    /// Every record in the log, oldest first, along with its offset.
+    pub fn iter(&self) -> LogIter<'_> {
+        LogIter::new(self.views(), 0, 0)
+    }
+
+    /// The records from `offset` on, where `offset` is one that
+    /// [`append`](MmapLog::append) returned (or [`end_offset`](MmapLog::end_offset)).
+    ///
+    /// Returns `None` if no record starts at `offset`, say because it's outside
+    /// the log or points into the middle of a record.
+    pub fn iter_from(&self, offset: u64) -> Option<LogIter<'_>> {
+        LogIter::starting_at(self.views(), offset)
+    }
+
+    /// Writes every segment's dirty pages back to disk and waits for it to finish.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.segments.iter().try_for_each(|s| s.flush())
+    }
+
+    /// Starts a new, empty segment at `base`.
+    ///
+    /// Opening only scans the newest segment, so the one being
+    /// left behind is flushed first to make sure it's all on disk.
+    fn roll(&mut self, base: u64) -> Result<(), MmapCellError> {
+        if let Some(last) = self.segments.last() {
+            last.flush()?;
+        }
+
+        let segment = segment_options()
+            .create(true)
+            .trailing(self.segment_size)
+            .initializer(move || SegmentHeader { base })
+            .open(self.dir.join(format!("{base:020}.log")))?;
+
+        self.segments.push(segment);
+        self.end = 0;
+
+        Ok(())
+    }
+
+    fn views(&self) -> Vec<(u64, &[u8])> {
+        self.segments
+            .iter()
+            .map(|s| (s.get().base, s.trailing()))
+            .collect()
+    }
+
+    fn last(&self) -> &MmapCell<SegmentHeader> {
+        self.segments.last().expect("a log always has a segment")
+    }
+
+    fn last_mut(&mut self) -> &mut MmapCell<SegmentHeader> {
+        self.segments
+            .last_mut()
+            .expect("a log always has a segment")
+    }
+}
+
+/// How the writer opens every segment file, the lock is what keeps a second writer out.
+fn segment_options() -> MmapCellOptions<SegmentHeader> {
+    reader_segment_options().lock(LockMode::TryExclusive(Duration::ZERO))
+}
+
+/// How a reader opens every segment file, a shared lock would only ever clash with the writer.
+fn reader_segment_options() -> MmapCellOptions<SegmentHeader> {
+    MmapCell::options()
+        .header(SEGMENT_FINGERPRINT)
+        .size_policy(SizePolicy::ExposeTrailing)
+}
+
+/// A read-only view of the log in a directory, opened by [`MmapLog::open_reader`].
+///
+/// Readers take no lock, so they can be opened while a [`MmapLog`] is appending
+/// to the same directory. They see records as the writer appends them, a record
+/// only turns up once its checksum matches so a half written one never does.
+/// Segments the writer starts after the reader was opened are only picked
+/// up by [`refresh`](MmapLogReader::refresh).
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapLog;
+///
+/// let dir = "/tmp/mmapcell-log-reader-doc-test";
+/// let _ = std::fs::remove_dir_all(dir);
+///
+/// let mut log = MmapLog::open(dir, 4096).unwrap();
+/// let reader = MmapLog::open_reader(dir).unwrap();
+///
+/// let offset = log.append(b"order 1 filled").unwrap();
+/// assert_eq!(reader.iter().next(), Some((offset, &b"order 1 filled"[..])));
+/// ```
+pub struct MmapLogReader {
+    dir: PathBuf,
+    /// oldest first
+    segments: Vec<MmapCellRef<SegmentHeader>>,
+}
+
+impl MmapLogReader {
+    /// Maps any segments the writer started since the reader was opened or last refreshed.
+    pub fn refresh(&mut self) -> Result<(), MmapCellError> {
+        let newest = self.segments.last().map(|s| s.get().base);
+        let mut found = Vec::new();
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == "log") {
+                let segment = reader_segment_options().open_readonly(&path)?;
+
+                if newest.is_none_or(|newest| segment.get().base > newest) {
+                    found.push(segment);
+                }
+            }
+        }
+
+        found.sort_by_key(|s| s.get().base);
+        self.segments.extend(found);
+
+        Ok(())
+    }
+
+    /// Offset of the oldest record in the log, if it has any segments yet.
+    pub fn start_offset(&self) -> Option<u64> {
+        self.segments.first().map(|s| s.get().base)
+    }
+
+    /// How many segment files the reader has mapped.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Every record in the log, oldest first, along with its offset.
+    pub fn iter(&self) -> LogIter<'_> {
+        LogIter::new(self.views(), 0, 0)
+    }
+
+    /// The records from `offset` on, the same as [`MmapLog::iter_from`].
+    pub fn iter_from(&self, offset: u64) -> Option<LogIter<'_>> {
+        LogIter::starting_at(self.views(), offset)
+    }
+
+    fn views(&self) -> Vec<(u64, &[u8])> {
+        self.segments
+            .iter()
+            .map(|s| (s.get().base, s.trailing()))
+            .collect()
+    }
+}
+
+/// Iterator over the records of a [`MmapLog`] and their offsets, created by
+/// [`MmapLog::iter`] and [`MmapLog::iter_from`] or their [`MmapLogReader`] equivalents.
+pub struct LogIter<'a> {
+    /// the base and records of every segment, oldest first
+    segments: Vec<(u64, &'a [u8])>,
+    segment: usize,
+    at: usize,
+}
+
+impl<'a> LogIter<'a> {
+    fn new(segments: Vec<(u64, &'a [u8])>, segment: usize, at: usize) -> LogIter<'a> {
+        LogIter {
+            segments,
+            segment,
+            at,
+        }
+    }
+
+    /// Starts at `offset` if a record starts there or it's where the records end.
+    fn starting_at(segments: Vec<(u64, &'a [u8])>, offset: u64) -> Option<LogIter<'a>> {
+        let segment = segments
+            .partition_point(|&(base, _)| base <= offset)
+            .checked_sub(1)?;
+        let (base, bytes) = segments[segment];
+        let at = usize::try_from(offset - base).ok()?;
+
+        // the checksum gives away anything that isn't a record
+        if read_record(bytes, at).is_none() {
+            // so the only other place it can start is right after the last
+            // one, which is only the end of the log in the newest segment
+            if segment + 1 != segments.len() {
+                return None;
+            }
+
+            let mut end = 0;
+            while let Some(record) = read_record(bytes, end).filter(|_| end < at) {
+                end += RECORD_HEADER + record.len();
+            }
+
+            if end != at {
+                return None;
+            }
+        }
+
+        Some(LogIter::new(segments, segment, at))
+    }
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = (u64, &'a [u8]);
+
+    fn next(&mut self) -> Option<(u64, &'a [u8])> {
+        loop {
+            let &(base, bytes) = self.segments.get(self.segment)?;
+
+            if let Some(record) = read_record(bytes, self.at) {
+                let offset = base + self.at as u64;
+                self.at += RECORD_HEADER + record.len();
+
+                return Some((offset, record));
+            }
+
+            // the rest of the segment didn't fit the next record
+            self.segment += 1;
+            self.at = 0;
+        }
+    }
+}
+
+/// The payload of the record at `at`, if there's a whole one there with a good checksum.
+fn read_record(bytes: &[u8], at: usize) -> Option<&[u8]> {
+    let header = bytes.get(at..at.checked_add(RECORD_HEADER)?)?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+
+    let start = at + RECORD_HEADER;
+    let record = bytes.get(start..start.checked_add(len)?)?;
+
+    match !crc32(crc32(!0, &header[..4]), record) == checksum {
+        true => Some(record),
+        false => None,
+    }
+}
+
+/// CRC-32 (the zlib/ethernet one), without the final inversion so it can be chained.
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+
+            while k < 8 {
+                c = match c & 1 {
+                    1 => 0xedb8_8320 ^ (c >> 1),
+                    _ => c >> 1,
+                };
+                k += 1;
+            }
+
+            table[i] = c;
+            i += 1;
+        }
+
+        table
+    };
+
+    for &b in bytes {
+        crc = TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Header;
+
+    #[test]
+    fn rolls_and_recovers() {
+        assert_eq!(!crc32(!0, b"123456789"), 0xcbf4_3926);
+
+        let dir = std::env::temp_dir().join("mmapcell-log-test");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // room for three 24 byte records per segment
+        let mut log = MmapLog::open(&dir, 80).unwrap();
+        assert!(log.append(&[0; 80]).is_err());
+
+        let offsets: Vec<_> = (0..7u64)
+            .map(|i| log.append(&[i as u8; 16]).unwrap())
+            .collect();
+
+        assert_eq!(offsets, [0, 24, 48, 72, 96, 120, 144]);
+        assert_eq!(log.segments(), 3);
+        drop(log);
+
+        // tear the last record, as if the writer died halfway through it
+        let last = dir.join(format!("{:020}.log", 144));
+        let mut raw = std::fs::read(&last).unwrap();
+        let data = Header::data_offset::<SegmentHeader>() + size_of::<SegmentHeader>();
+        raw[data + RECORD_HEADER + 3] ^= 0xff;
+        std::fs::write(&last, &raw).unwrap();
+
+        let mut log = MmapLog::open(&dir, 80).unwrap();
+        assert_eq!(log.end_offset(), 144);
+
+        assert!(matches!(
+            MmapLog::open(&dir, 80),
+            Err(MmapCellError::Locked)
+        ));
+        assert_eq!(log.append(b"again").unwrap(), 144);
+
+        let records: Vec<_> = log
+            .iter_from(72)
+            .unwrap()
+            .map(|(o, r)| (o, r.to_vec()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (72, vec![3; 16]),
+                (96, vec![4; 16]),
+                (120, vec![5; 16]),
+                (144, b"again".to_vec())
+            ]
+        );
+        assert_eq!(log.iter().count(), 7);
+
+        // offsets that aren't where a record starts are turned away
+        assert_eq!(log.iter_from(157).unwrap().count(), 0);
+        assert!(log.iter_from(80).is_none());
+        assert!(log.iter_from(158).is_none());
+        drop(log);
+
+        // a smaller segment size only matters once a new segment is needed
+        let mut log = MmapLog::open(&dir, 16).unwrap();
+        assert_eq!(log.append(&[9; 16]).unwrap(), 157);
+        assert_eq!(log.append(&[9; 16]).unwrap(), 181);
+        assert!(matches!(
+            log.append(&[9; 16]),
+            Err(MmapCellError::Layout(LayoutError::LengthOutOfBounds {
+                len: 24,
+                capacity: 16
+            }))
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reader_alongside_writer() {
+        let dir = std::env::temp_dir().join("mmapcell-log-reader-test");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut log = MmapLog::open(&dir, 80).unwrap();
+        log.append(&[0; 16]).unwrap();
+
+        let mut reader = MmapLog::open_reader(&dir).unwrap();
+        let mut other = MmapLog::open_reader(&dir).unwrap();
+
+        // only the writer is kept out
+        assert!(matches!(
+            MmapLog::open(&dir, 80),
+            Err(MmapCellError::Locked)
+        ));
+
+        // records in mapped segments show up as they're appended
+        log.append(&[1; 16]).unwrap();
+        assert_eq!(reader.iter().count(), 2);
+        assert_eq!(reader.iter_from(48).unwrap().count(), 0);
+        assert!(reader.iter_from(72).is_none());
+
+        // new segments only once refreshed
+        for i in 2..5 {
+            log.append(&[i; 16]).unwrap();
+        }
+        assert_eq!(reader.iter().count(), 3);
+        assert!(reader.iter_from(96).is_none());
+
+        reader.refresh().unwrap();
+        assert_eq!(reader.segments(), 2);
+        assert_eq!(reader.start_offset(), Some(0));
+        assert_eq!(
+            reader.iter_from(72).unwrap().collect::<Vec<_>>(),
+            [(72, &[3; 16][..]), (96, &[4; 16][..])]
+        );
+        assert_eq!(reader.iter_from(120).unwrap().count(), 0);
+
+        // readers don't hold the writer up either
+        drop(log);
+        other.refresh().unwrap();
+        let mut log = MmapLog::open(&dir, 80).unwrap();
+        assert_eq!(log.append(b"again").unwrap(), 120);
+        assert_eq!(other.iter().count(), 6);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
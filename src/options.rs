@@ -0,0 +1,758 @@
+use memmap2::MmapOptions;
+use std::{
+    fs::File,
+    io::ErrorKind,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    lock::FileLock, Header, LayoutError, LockMode, MmapCell, MmapCellError, MmapCellRef, MmapPod,
+    MmapSafe, SizePolicy,
+};
+
+/// What a cell does with its mapping when it's dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushOnDrop {
+    /// leave writeback entirely up to the kernel
+    Never,
+    /// start writing dirty pages back but don't wait for it
+    Async,
+    /// write dirty pages back and wait for it to finish
+    #[default]
+    Sync,
+}
+
+/// Builder for every way of opening or creating an [`MmapCell`].
+///
+/// Wraps [`memmap2::MmapOptions`] along with the file and cell level settings,
+/// the [`MmapCell`] constructors are all shorthands for some combination of these.
+///
+/// By default an existing file is opened as is, exactly like [`MmapCell::open_named`].
+///
+/// # Example
+/// ```rust
+/// use mmapcell::{LockMode, MmapCell};
+///
+/// let cell = MmapCell::<[u64; 4]>::options()
+///     .create(true)
+///     .mode(0o600)
+///     .lock(LockMode::Exclusive)
+///     .initializer(|| [1, 2, 3, 4])
+///     .open("/tmp/mmapcell-options-doc-test.bin")
+///     .unwrap();
+///
+/// assert_eq!(cell.get()[0], 1);
+/// ```
+pub struct MmapCellOptions<T> {
+    mmap: MmapOptions,
+    create: bool,
+    create_new: bool,
+    resize: bool,
+    mode: Option<u32>,
+    offset: u64,
+    trailing: usize,
+    lock: Option<LockMode>,
+    flush_on_drop: FlushOnDrop,
+    policy: SizePolicy,
+    header: Option<Header>,
+    init: Option<Box<dyn FnOnce() -> T>>,
+    entries: Option<Entries<T>>,
+    _inner: PhantomData<T>,
+}
+
+/// How the containers built on a cell size a file from the value it's created
+/// with: `offset` bytes and then `count(&value)` entries of `size` bytes.
+struct Entries<T> {
+    count: fn(&T) -> usize,
+    offset: usize,
+    size: usize,
+}
+
+/// What it takes to map a cell's file again after growing it.
+pub(crate) struct Remap {
+    file: File,
+    /// already set to `offset`
+    mmap: MmapOptions,
+    offset: u64,
+}
+
+impl Remap {
+    /// Sets the file to hold exactly `trailing` bytes past the `T`
+    /// and maps it again in place of the cell's current mapping.
+    pub(crate) fn grow<T>(
+        &self,
+        cell: &mut MmapCell<T>,
+        trailing: usize,
+    ) -> Result<(), MmapCellError> {
+        let len = (cell.offset + size_of::<T>())
+            .checked_add(trailing)
+            .ok_or_else(overflow)?;
+
+        self.file.set_len(self.offset + len as u64)?;
+
+        // SAFETY: same file and offset as before, only longer
+        cell.raw = unsafe { self.mmap.map_mut(&self.file)? };
+        cell.trailing = trailing;
+
+        Ok(())
+    }
+}
+
+impl<T> Default for MmapCellOptions<T> {
+    fn default() -> Self {
+        MmapCellOptions::new()
+    }
+}
+
+impl<T> MmapCellOptions<T> {
+    pub fn new() -> MmapCellOptions<T> {
+        MmapCellOptions {
+            mmap: MmapOptions::new(),
+            create: false,
+            create_new: false,
+            resize: false,
+            mode: None,
+            offset: 0,
+            trailing: 0,
+            lock: None,
+            flush_on_drop: FlushOnDrop::default(),
+            policy: SizePolicy::default(),
+            header: None,
+            init: None,
+            entries: None,
+            _inner: PhantomData,
+        }
+    }
+
+    /// Create the file if it doesn't exist yet.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file and fail if it already exists.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Set the file to exactly the size the cell needs even if it already exists.
+    ///
+    /// This is what [`MmapCell::new_named`] does.
+    pub fn resize(mut self, resize: bool) -> Self {
+        self.resize = resize;
+        self
+    }
+
+    /// Permission bits for a newly created file, subject to the umask.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Map the file starting `offset` bytes in rather than at the beginning.
+    ///
+    /// The offset doesn't have to be page aligned but `T` still has to be
+    /// aligned in memory, so in practice it should be a multiple of `align_of::<T>()`.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Leave room for `len` bytes past the cell in a file this creates or resizes.
+    ///
+    /// Only useful along with a [`SizePolicy`] that allows trailing bytes,
+    /// [`SizePolicy::ExposeTrailing`] makes them available through [`MmapCell::trailing`].
+    pub fn trailing(mut self, len: usize) -> Self {
+        self.trailing = len;
+        self
+    }
+
+    /// Prefault the whole mapping up front (`MAP_POPULATE`).
+    pub fn populate(mut self) -> Self {
+        self.mmap.populate();
+        self
+    }
+
+    /// Back the mapping with huge pages (`MAP_HUGETLB`), optionally of
+    /// `1 << page_bits` bytes instead of the system default.
+    ///
+    /// Only anonymous mappings and files on a hugetlbfs can use huge pages.
+    pub fn huge(mut self, page_bits: Option<u8>) -> Self {
+        self.mmap.huge(page_bits);
+        self
+    }
+
+    /// Hold a `flock` on the file for as long as the cell lives.
+    pub fn lock(mut self, mode: LockMode) -> Self {
+        self.lock = Some(mode);
+        self
+    }
+
+    /// What to do with the mapping when the cell is dropped, defaults to [`FlushOnDrop::Sync`].
+    pub fn flush_on_drop(mut self, flush: FlushOnDrop) -> Self {
+        self.flush_on_drop = flush;
+        self
+    }
+
+    /// What to do with a file that is larger than the cell needs, defaults to [`SizePolicy::Strict`].
+    pub fn size_policy(mut self, policy: SizePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Start the file with a [`Header`] describing `T` and `fingerprint`.
+    pub fn header(mut self, fingerprint: u64) -> Self {
+        self.header = Some(Header::new::<T>(fingerprint));
+        self
+    }
+
+    /// Value to write into a file this creates (or into an anonymous mapping)
+    /// instead of leaving it zeroed.
+    ///
+    /// New files are filled in under a scratch name and only linked into place once
+    /// initialized, so the initializer runs exactly once even when several processes
+    /// race to create the same file and none of them ever sees it half done.
+    pub fn initializer<F: FnOnce() -> T + 'static>(mut self, init: F) -> Self {
+        self.init = Some(Box::new(init));
+        self
+    }
+
+    /// Sizes the trailing bytes of a file this creates as `offset + count(&value) * size`,
+    /// where `value` is what the file is initialized with.
+    pub(crate) fn trailing_entries(
+        mut self,
+        count: fn(&T) -> usize,
+        offset: usize,
+        size: usize,
+    ) -> Self {
+        self.entries = Some(Entries {
+            count,
+            offset,
+            size,
+        });
+        self
+    }
+
+    /// The same options for a cell of another type, minus anything tied to `T`
+    /// (the initializer and how entries are sized).
+    pub(crate) fn retype<U>(self) -> MmapCellOptions<U> {
+        MmapCellOptions {
+            mmap: self.mmap,
+            create: self.create,
+            create_new: self.create_new,
+            resize: self.resize,
+            mode: self.mode,
+            offset: self.offset,
+            trailing: self.trailing,
+            lock: self.lock,
+            flush_on_drop: self.flush_on_drop,
+            policy: self.policy,
+            header: self.header.map(|h| Header::new::<U>(h.fingerprint)),
+            init: None,
+            entries: None,
+            _inner: PhantomData,
+        }
+    }
+
+    /// Has the header describe `E` rather than `T`, for containers
+    /// whose `T` only stands in for the elements they hold.
+    pub(crate) fn describe<E>(mut self) -> Self {
+        self.header = self.header.map(|h| Header::new::<E>(h.fingerprint));
+        self
+    }
+
+    /// What the size policy was set to, for containers that
+    /// apply it to their entries rather than the cell.
+    pub(crate) fn get_size_policy(&self) -> SizePolicy {
+        self.policy
+    }
+
+    /// where T starts relative to the start of the mapping
+    pub(crate) fn data_offset(&self) -> usize {
+        match self.header {
+            Some(_) => Header::data_offset::<T>(),
+            None => 0,
+        }
+    }
+
+    fn open_file(&self, path: &Path, write: bool) -> Result<File, std::io::Error> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(write)
+            .truncate(false)
+            .open(path)
+    }
+
+    /// opens the file next to `path` called `.{name}.mmapcell-{suffix}`, creating it if needed
+    fn open_sibling(&self, path: &Path, suffix: &str) -> Result<(PathBuf, File), std::io::Error> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let sibling = path.with_file_name(format!(".{name}.mmapcell-{suffix}"));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+
+        if let Some(mode) = self.mode {
+            options.mode(mode);
+        }
+
+        let file = options.open(&sibling)?;
+
+        Ok((sibling, file))
+    }
+
+    fn acquire_lock(&self, file: &File) -> Result<Option<FileLock>, MmapCellError> {
+        self.lock
+            .map(|mode| FileLock::acquire(file, mode))
+            .transpose()
+    }
+
+    /// Opens (or creates) the file at `path` as a writable cell.
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<MmapCell<T>, MmapCellError>
+    where
+        T: MmapSafe,
+    {
+        // SAFETY: T is MmapSafe so whatever bytes are mapped are a valid T
+        unsafe { self.open_unchecked(path) }
+    }
+
+    /// # Safety
+    /// whatever is in the file (or zeroes if it's created without an
+    /// initializer) must be a valid T
+    pub(crate) unsafe fn open_unchecked<P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> Result<MmapCell<T>, MmapCellError> {
+        unsafe { self.open_file_and_cell(path.as_ref()) }.map(|(cell, _)| cell)
+    }
+
+    /// Like [`open`](MmapCellOptions::open) but also hands back what it
+    /// takes to grow the file later on.
+    pub(crate) fn open_growable<P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> Result<(MmapCell<T>, Remap), MmapCellError>
+    where
+        T: MmapSafe,
+    {
+        let mut mmap = self.mmap.clone();
+        mmap.offset(self.offset);
+        let offset = self.offset;
+
+        // SAFETY: T is MmapSafe so whatever bytes are mapped are a valid T
+        let (cell, file) = unsafe { self.open_file_and_cell(path.as_ref())? };
+
+        Ok((cell, Remap { file, mmap, offset }))
+    }
+
+    /// # Safety
+    /// see [`open_unchecked`](MmapCellOptions::open_unchecked)
+    unsafe fn open_file_and_cell(
+        mut self,
+        path: &Path,
+    ) -> Result<(MmapCell<T>, File), MmapCellError> {
+        loop {
+            if !self.create_new {
+                match self.open_file(path, true) {
+                    Ok(file) => {
+                        let lock = self.acquire_lock(&file)?;
+
+                        // only ever fresh if this call created it, an existing file that's
+                        // empty (say from a `touch`) is just too small
+                        let mut cell = unsafe { self.map_file(&file, false)? };
+                        cell._lock = lock;
+
+                        return Ok((cell, file));
+                    }
+                    Err(e) if e.kind() == ErrorKind::NotFound && self.create => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            if let Some(created) = unsafe { self.create_atomically(path)? } {
+                return Ok(created);
+            }
+
+            // somebody else created it first, go back around and open theirs
+        }
+    }
+
+    /// Fills in a scratch file completely (header, initializer and all) and only then
+    /// links it into place at `path`, so that nobody opening `path` can ever see a half
+    /// initialized `T`.
+    ///
+    /// Creators serialize on a `flock` of a separate lock file and check for `path` once
+    /// they hold it, so the initializer runs exactly once no matter how many processes
+    /// race. Nobody but the lock holder ever opens the scratch file, so no creator ever
+    /// ends up holding a lock on what became `path` and getting in the way of the
+    /// [`lock`](MmapCellOptions::lock) its opener asked for. A creator that dies halfway
+    /// just leaves the scratch file behind for the next one to start over with.
+    ///
+    /// Returns `None` if some other process won the race to create `path`.
+    ///
+    /// # Safety
+    /// zeroes (or the initializer) must be a valid T
+    unsafe fn create_atomically(
+        &mut self,
+        path: &Path,
+    ) -> Result<Option<(MmapCell<T>, File)>, MmapCellError> {
+        let (lock_path, lock_file) = self.open_sibling(path, "lock")?;
+        let creating = FileLock::acquire(&lock_file, LockMode::Exclusive)?;
+
+        // the lock file is removed (under the lock) once `path` exists, whoever was
+        // waiting on the removed one has to go around and look at `path` again
+        if !is_same_file(&lock_file, &lock_path)? {
+            return Ok(None);
+        }
+
+        match std::fs::metadata(path) {
+            Ok(_) => {
+                let _ = std::fs::remove_file(&lock_path);
+
+                return match self.create_new {
+                    true => Err(std::io::Error::from(ErrorKind::AlreadyExists).into()),
+                    false => Ok(None),
+                };
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let (temp, file) = self.open_sibling(path, "tmp")?;
+
+        // throw away whatever a dead creator left behind
+        file.set_len(0)?;
+
+        let mut cell = unsafe { self.map_file(&file, true)? };
+
+        std::fs::hard_link(&temp, path)?;
+        std::fs::remove_file(&temp)?;
+        std::fs::remove_file(&lock_path)?;
+
+        drop(creating);
+        cell._lock = self.acquire_lock(&file)?;
+
+        Ok(Some((cell, file)))
+    }
+
+    /// # Safety
+    /// whatever is in the file (or zeroes/the initializer if `fresh`) must be a valid T
+    unsafe fn map_file(&mut self, file: &File, fresh: bool) -> Result<MmapCell<T>, MmapCellError> {
+        let data_offset = self.data_offset();
+
+        let value = match fresh {
+            true => self.init.take().map(|init| init()),
+            false => None,
+        };
+
+        let trailing = match &self.entries {
+            Some(entries) if fresh => {
+                let count = match &value {
+                    Some(value) => (entries.count)(value),
+                    // SAFETY: a fresh file is zeroes, which the caller promised are a valid T
+                    None => (entries.count)(&ManuallyDrop::new(unsafe { std::mem::zeroed() })),
+                };
+
+                count
+                    .checked_mul(entries.size)
+                    .and_then(|len| len.checked_add(entries.offset))
+                    .ok_or_else(overflow)?
+            }
+            _ => self.trailing,
+        };
+
+        let len = (data_offset + size_of::<T>())
+            .checked_add(trailing)
+            .ok_or_else(overflow)?;
+
+        if fresh || self.resize {
+            if let (false, Some(header)) = (fresh, self.header) {
+                // a file holding something else has to be turned away before it's resized
+                let available = file.metadata()?.len().saturating_sub(self.offset);
+                let mut bytes = [0; Header::LEN];
+                let bytes = &mut bytes[..available.min(Header::LEN as u64) as usize];
+
+                file.read_exact_at(bytes, self.offset)?;
+                header.verify(bytes)?;
+            }
+
+            file.set_len(self.offset + len as u64)?;
+        } else {
+            // check before mapping so a short file isn't mistaken for a bad header
+            let expected = data_offset + size_of::<T>();
+            let actual = file.metadata()?.len().saturating_sub(self.offset) as usize;
+
+            if actual < expected {
+                return Err(LayoutError::TooSmall { expected, actual }.into());
+            }
+        }
+
+        let mut m = unsafe { self.mmap.offset(self.offset).map_mut(file)? };
+
+        match self.header {
+            Some(header) if fresh => header.write(&mut m),
+            Some(header) => header.verify(&m)?,
+            None => {}
+        }
+
+        let mut cell = unsafe { MmapCell::<T>::from_parts(m, data_offset, self.policy)? };
+        cell.flush = self.flush_on_drop;
+
+        if let Some(value) = value {
+            unsafe { cell.init(value) };
+        }
+
+        Ok(cell)
+    }
+
+    /// Creates an anonymous cell, only the mmap level settings,
+    /// flushing and the initializer apply.
+    pub fn open_anon(mut self) -> Result<MmapCell<T>, MmapCellError>
+    where
+        T: MmapSafe,
+    {
+        let m = self.mmap.len(size_of::<T>()).map_anon()?;
+
+        let mut cell = MmapCell::<T>::new(m)?;
+        cell.flush = self.flush_on_drop;
+
+        if let Some(init) = self.init.take() {
+            // SAFETY: T is MmapSafe and the mapping was just created
+            unsafe { cell.init(init()) };
+        }
+
+        Ok(cell)
+    }
+
+    /// Opens an existing file at `path` as a read-only cell,
+    /// creation, resizing, flushing and the initializer don't apply.
+    pub fn open_readonly<P: AsRef<Path>>(self, path: P) -> Result<MmapCellRef<T>, MmapCellError>
+    where
+        T: MmapPod,
+    {
+        let file = self.open_file(path.as_ref(), false)?;
+        let lock = self.acquire_lock(&file)?;
+
+        let m = unsafe { self.mmap.clone().offset(self.offset).map(&file)? };
+
+        if let Some(header) = self.header {
+            header.verify(&m)?;
+        }
+
+        MmapCellRef::from_parts(m, self.data_offset(), self.policy, lock)
+    }
+}
+
+/// whether `file` is still the one at `path`, rather than one that was removed from it
+fn is_same_file(file: &File, path: &Path) -> Result<bool, std::io::Error> {
+    let opened = file.metadata()?;
+
+    match std::fs::metadata(path) {
+        Ok(current) => Ok((opened.dev(), opened.ino()) == (current.dev(), current.ino())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn overflow() -> std::io::Error {
+    std::io::Error::new(ErrorKind::OutOfMemory, "length overflow")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_header_and_initializer() {
+        let path = std::env::temp_dir().join("mmapcell-options-test.bin");
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&path, [0xaa; 8]).unwrap();
+
+        // an existing file with nothing past the offset is too small, not new
+        assert!(matches!(
+            MmapCellOptions::<u64>::new()
+                .create(true)
+                .offset(8)
+                .header(3)
+                .initializer(|| 42)
+                .open(&path),
+            Err(MmapCellError::Layout(LayoutError::TooSmall {
+                expected: 48,
+                actual: 0
+            }))
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), [0xaa; 8]);
+        std::fs::remove_file(&path).unwrap();
+
+        let cell = MmapCellOptions::<u64>::new()
+            .create(true)
+            .offset(8)
+            .header(3)
+            .initializer(|| 42)
+            .open(&path)
+            .unwrap();
+        assert_eq!(*cell.get(), 42);
+        drop(cell);
+
+        let mut raw = std::fs::read(&path).unwrap();
+        raw[..8].fill(0xaa);
+        std::fs::write(&path, raw).unwrap();
+
+        // the initializer only runs on a fresh file
+        let cell = MmapCellOptions::<u64>::new()
+            .offset(8)
+            .header(3)
+            .initializer(|| 7)
+            .open(&path)
+            .unwrap();
+        assert_eq!(*cell.get(), 42);
+        drop(cell);
+
+        let reader = MmapCellOptions::<u64>::new()
+            .offset(8)
+            .header(3)
+            .open_readonly(&path)
+            .unwrap();
+        assert_eq!(*reader.get(), 42);
+
+        // bytes before the offset are left alone
+        assert_eq!(std::fs::read(&path).unwrap()[..8], [0xaa; 8]);
+
+        assert!(MmapCellOptions::<u64>::new()
+            .create_new(true)
+            .open(&path)
+            .is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn mismatched_header_isnt_resized() {
+        use crate::HeaderError;
+
+        let path = std::env::temp_dir().join("mmapcell-options-resize-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let options = || MmapCellOptions::<u64>::new().create(true).resize(true);
+        drop(options().header(3).open(&path).unwrap());
+
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        assert!(matches!(
+            options().header(4).trailing(64).open(&path),
+            Err(MmapCellError::Header(
+                HeaderError::FingerprintMismatch { .. }
+            ))
+        ));
+        assert!(matches!(
+            MmapCellOptions::<u32>::new()
+                .resize(true)
+                .header(3)
+                .open(&path),
+            Err(MmapCellError::Header(HeaderError::SizeMismatch { .. }))
+        ));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        // with the right header it's resized as asked
+        let cell = options()
+            .header(3)
+            .trailing(64)
+            .size_policy(SizePolicy::AllowTrailing)
+            .open(&path)
+            .unwrap();
+        drop(cell);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len + 64);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn racing_initializers_run_once() {
+        use std::sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc, Barrier,
+        };
+
+        let path = std::env::temp_dir().join("mmapcell-options-race-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        static RUNS: AtomicU32 = AtomicU32::new(0);
+        let barrier = Arc::new(Barrier::new(8));
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|i| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+
+                std::thread::spawn(move || {
+                    barrier.wait();
+
+                    let cell = MmapCell::open_or_create(&path, move || {
+                        RUNS.fetch_add(1, Ordering::SeqCst);
+                        [i + 1; 64]
+                    })
+                    .unwrap();
+
+                    // whoever won, every opener sees their fully written value
+                    let first = cell.get()[0];
+                    assert!(first != 0 && cell.get().iter().all(|&v| v == first));
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn racing_creators_with_locks() {
+        use std::{
+            sync::{mpsc, Arc, Barrier},
+            time::Duration,
+        };
+
+        let path = std::env::temp_dir().join("mmapcell-options-lock-race-test.bin");
+
+        for _ in 0..200 {
+            let _ = std::fs::remove_file(&path);
+
+            let (tx, rx) = mpsc::channel();
+            let (start, done) = (Arc::new(Barrier::new(8)), Arc::new(Barrier::new(8)));
+
+            for _ in 0..8 {
+                let (path, tx) = (path.clone(), tx.clone());
+                let (start, done) = (start.clone(), done.clone());
+
+                std::thread::spawn(move || {
+                    start.wait();
+
+                    // a creator left holding a lock on the new file would
+                    // make this fail or block while the others hold theirs
+                    let cell = MmapCell::<u64>::new_named_locked(
+                        &path,
+                        LockMode::TryShared(Duration::ZERO),
+                    );
+                    tx.send(cell.is_ok()).unwrap();
+
+                    done.wait();
+                });
+            }
+
+            for _ in 0..8 {
+                let opened = rx
+                    .recv_timeout(Duration::from_secs(10))
+                    .expect("an opener got stuck");
+                assert!(opened);
+            }
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
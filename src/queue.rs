@@ -0,0 +1,468 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    ring::CachePadded,
+    sync::{current_tid, is_alive},
+    LayoutError, MmapCellError, MmapHeaderSlice, MmapSafe,
+};
+
+/// the low half of a slot word says what's going on with it,
+/// the high half is the first position of the lap it's at
+const EMPTY: u32 = 0;
+const FULL: u32 = u32::MAX;
+/// left by whoever finds a slot its writer died in the middle of
+const SKIP: u32 = u32::MAX - 1;
+/// set along with the reader's thread id, a bare thread id is a writer
+const READING: u32 = 1 << 30;
+
+#[repr(C)]
+struct QueueHeader {
+    capacity: u32,
+    _pad: [u32; 15],
+    /// next position to write, can lag behind and is helped along by everyone
+    tail: CachePadded,
+    /// next position to read, can lag behind and is helped along by everyone
+    head: CachePadded,
+}
+
+// SAFETY: a whole cache line of plain words and then two more,
+// any capacity that isn't a power of two is rejected on open
+unsafe impl MmapSafe for QueueHeader {}
+
+#[repr(C)]
+struct Slot<T> {
+    word: AtomicU64,
+    value: UnsafeCell<T>,
+}
+
+// Slot isn't MmapSafe, there can be padding after `value`. The queue maps its slots as
+// plain words instead and only ever writes them field by field, so the padding keeps
+// whatever bytes the file had.
+
+fn word(lap: u32, state: u32) -> u64 {
+    (lap as u64) << 32 | state as u64
+}
+
+fn split(word: u64) -> (u32, u32) {
+    ((word >> 32) as u32, word as u32)
+}
+
+/// A multi producer, multi consumer bounded queue in a file, after Dmitry Vyukov's
+/// bounded MPMC queue.
+///
+/// Any number of processes can push and pop at once. Every slot records the thread
+/// that's writing or reading it, so a slot whose writer died halfway through is
+/// skipped instead of blocking everybody behind it, and one whose reader died is
+/// handed back to the producers. Whatever the dead thread was writing or reading is
+/// lost either way, so delivery is at most once.
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapQueue;
+///
+/// let path = "/tmp/mmapcell-queue-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+///
+/// let dispatcher = MmapQueue::<u64>::open(path, 256).unwrap();
+/// let worker = MmapQueue::<u64>::open(path, 256).unwrap();
+///
+/// dispatcher.try_push(17).unwrap();
+///
+/// assert_eq!(worker.try_pop(), Some(17));
+/// assert_eq!(worker.try_pop(), None);
+/// ```
+pub struct MmapQueue<T> {
+    /// the slots, as words since a `Slot` can have padding
+    slice: MmapHeaderSlice<QueueHeader, u64>,
+    _inner: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Sync for MmapQueue<T> {}
+unsafe impl<T: Send> Send for MmapQueue<T> {}
+
+impl<T: MmapSafe> MmapQueue<T> {
+    /// Opens the queue at `path`, creating an empty one with room for
+    /// `capacity` elements if the file doesn't exist yet.
+    ///
+    /// `capacity` has to be a power of two and match the existing queue, if any.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: u32) -> Result<MmapQueue<T>, MmapCellError> {
+        if !capacity.is_power_of_two() || capacity > 1 << 30 {
+            return Err(LayoutError::BadCapacity {
+                capacity: capacity as usize,
+            }
+            .into());
+        }
+
+        let path = path.as_ref();
+        // slots are aligned to (and so a multiple of) 8 bytes
+        let count = |h: &QueueHeader| h.capacity as usize * size_of::<Slot<T>>() / 8;
+
+        // all zero slots are empty and waiting for the first lap
+        let header = QueueHeader {
+            capacity,
+            _pad: [0; 15],
+            tail: CachePadded::default(),
+            head: CachePadded::default(),
+        };
+
+        let slice = MmapHeaderSlice::open_or_create(path, header, count)?;
+
+        if slice.header().capacity != capacity {
+            return Err(LayoutError::BadCapacity {
+                capacity: slice.header().capacity as usize,
+            }
+            .into());
+        }
+
+        let addr = slice.entries_ptr() as usize;
+        if !addr.is_multiple_of(align_of::<Slot<T>>()) {
+            return Err(LayoutError::Misaligned {
+                align: align_of::<Slot<T>>(),
+                addr,
+            }
+            .into());
+        }
+
+        Ok(MmapQueue {
+            slice,
+            _inner: PhantomData,
+        })
+    }
+
+    /// The slot `pos` lands on.
+    fn slot(&self, pos: u32) -> &Slot<T> {
+        let mask = self.slice.header().capacity - 1;
+
+        // SAFETY: the file holds capacity slots, aligned as checked on open. Any bytes
+        // are a valid Slot since T is MmapSafe and padding can hold anything.
+        unsafe {
+            &*self
+                .slice
+                .entries_ptr()
+                .cast::<Slot<T>>()
+                .add((pos & mask) as usize)
+        }
+    }
+
+    /// Appends `value`, or hands it back if the queue is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let header = self.slice.header();
+        let mask = header.capacity - 1;
+        let tid = current_tid();
+
+        loop {
+            let pos = header.tail.value.load(Ordering::Acquire);
+            let slot = self.slot(pos);
+            let lap = pos & !mask;
+
+            let current = slot.word.load(Ordering::Acquire);
+            let (slot_lap, state) = split(current);
+
+            match (slot_lap.wrapping_sub(lap) as i32).signum() {
+                // still holding (or handing out) the previous lap
+                -1 => {
+                    if !release_dead_reader(slot, current, mask + 1) {
+                        return Err(value);
+                    }
+                }
+                0 if state == EMPTY => {
+                    if slot
+                        .word
+                        .compare_exchange(
+                            current,
+                            word(lap, tid),
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_err()
+                    {
+                        continue;
+                    }
+
+                    advance(&header.tail, pos);
+
+                    unsafe { slot.value.get().write(value) };
+                    slot.word.store(word(lap, FULL), Ordering::Release);
+
+                    return Ok(());
+                }
+                // somebody else got this one, make sure tail moves on from it
+                0 => {
+                    skip_dead_writer(slot, current);
+                    advance(&header.tail, pos);
+                }
+                // written and read already, tail just hasn't caught up
+                _ => advance(&header.tail, pos),
+            }
+        }
+    }
+
+    /// Removes the oldest element, if there is one that's done being written.
+    pub fn try_pop(&self) -> Option<T> {
+        let header = self.slice.header();
+        let mask = header.capacity - 1;
+        let tid = current_tid();
+
+        loop {
+            let pos = header.head.value.load(Ordering::Acquire);
+            let slot = self.slot(pos);
+            let lap = pos & !mask;
+            let next_lap = word(lap.wrapping_add(mask + 1), EMPTY);
+
+            let current = slot.word.load(Ordering::Acquire);
+            let (slot_lap, state) = split(current);
+
+            match (slot_lap.wrapping_sub(lap) as i32).signum() {
+                // the previous lap's reader is still at it, so nothing's been written here yet
+                -1 => {
+                    if !release_dead_reader(slot, current, mask + 1) {
+                        return None;
+                    }
+                }
+                0 if state == FULL => {
+                    if slot
+                        .word
+                        .compare_exchange(
+                            current,
+                            word(lap, READING | tid),
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_err()
+                    {
+                        continue;
+                    }
+
+                    advance(&header.head, pos);
+
+                    let value = unsafe { slot.value.get().read() };
+                    slot.word.store(next_lap, Ordering::Release);
+
+                    return Some(value);
+                }
+                0 if state == SKIP => {
+                    let _ = slot.word.compare_exchange(
+                        current,
+                        next_lap,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    );
+                }
+                0 if state == EMPTY => return None,
+                0 if reader(state).is_some() => {
+                    release_dead_reader(slot, current, mask + 1);
+                    advance(&header.head, pos);
+                }
+                // still being written, unless the writer is gone
+                0 => {
+                    if !skip_dead_writer(slot, current) {
+                        return None;
+                    }
+                }
+                // already read, head just hasn't caught up
+                _ => advance(&header.head, pos),
+            }
+        }
+    }
+}
+
+impl<T> MmapQueue<T> {
+    pub fn capacity(&self) -> usize {
+        self.slice.header().capacity as usize
+    }
+
+    /// Roughly how many elements are queued, it can be stale by
+    /// the time it returns with other processes at work.
+    pub fn len(&self) -> usize {
+        let header = self.slice.header();
+
+        let head = header.head.value.load(Ordering::Acquire);
+        let tail = header.tail.value.load(Ordering::Acquire);
+
+        (tail.wrapping_sub(head) as usize).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Moves `index` on from `pos`, unless somebody already has.
+fn advance(index: &CachePadded, pos: u32) {
+    let _ = index.value.compare_exchange(
+        pos,
+        pos.wrapping_add(1),
+        Ordering::AcqRel,
+        Ordering::Relaxed,
+    );
+}
+
+/// The thread id of whoever is reading a slot in `state`, if anyone.
+fn reader(state: u32) -> Option<u32> {
+    match state {
+        FULL | SKIP => None,
+        state if state & READING != 0 => Some(state & !READING),
+        _ => None,
+    }
+}
+
+/// The thread id of whoever is writing a slot in `state`, if anyone.
+fn writer(state: u32) -> Option<u32> {
+    match state {
+        EMPTY | FULL | SKIP => None,
+        state if state & READING != 0 => None,
+        state => Some(state),
+    }
+}
+
+/// Marks a slot to be skipped if the writer that claimed it died,
+/// returns whether the slot changed since `current` was read.
+fn skip_dead_writer<T>(slot: &Slot<T>, current: u64) -> bool {
+    let (lap, state) = split(current);
+
+    if writer(state).is_some_and(|tid| !is_alive(tid)) {
+        let _ = slot.word.compare_exchange(
+            current,
+            word(lap, SKIP),
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+    }
+
+    slot.word.load(Ordering::Acquire) != current
+}
+
+/// Hands a slot on to the next lap if the reader that claimed it died,
+/// returns whether the slot changed since `current` was read.
+fn release_dead_reader<T>(slot: &Slot<T>, current: u64, capacity: u32) -> bool {
+    let (lap, state) = split(current);
+
+    if reader(state).is_some_and(|tid| !is_alive(tid)) {
+        let _ = slot.word.compare_exchange(
+            current,
+            word(lap.wrapping_add(capacity), EMPTY),
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+    }
+
+    slot.word.load(Ordering::Acquire) != current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_across_mappings() {
+        let path = std::env::temp_dir().join("mmapcell-queue-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        MmapQueue::<u64>::open(&path, 16).unwrap();
+
+        let producers: Vec<_> = (0..3u64)
+            .map(|p| {
+                let queue = MmapQueue::<u64>::open(&path, 16).unwrap();
+
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        let mut value = p * 1000 + i;
+
+                        while let Err(v) = queue.try_push(value) {
+                            value = v;
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..3)
+            .map(|_| {
+                let queue = MmapQueue::<u64>::open(&path, 16).unwrap();
+
+                std::thread::spawn(move || {
+                    let mut seen = Vec::new();
+
+                    while seen.len() < 1000 {
+                        match queue.try_pop() {
+                            Some(v) => seen.push(v),
+                            None => std::thread::yield_now(),
+                        }
+                    }
+
+                    seen
+                })
+            })
+            .collect();
+
+        producers.into_iter().for_each(|p| p.join().unwrap());
+
+        let mut seen: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        seen.sort();
+
+        assert_eq!(seen, (0..3000).collect::<Vec<_>>());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn padded_slots() {
+        let path = std::env::temp_dir().join("mmapcell-queue-padded-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        // a u8 leaves 7 bytes of padding at the end of every slot
+        let queue = MmapQueue::<u8>::open(&path, 4).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 192 + 4 * 16);
+
+        for i in 0..3 {
+            queue.try_push(i).unwrap();
+        }
+        drop(queue);
+
+        let queue = MmapQueue::<u8>::open(&path, 4).unwrap();
+        assert_eq!([queue.try_pop(), queue.try_pop()], [Some(0), Some(1)]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_writer_and_reader() {
+        let path = std::env::temp_dir().join("mmapcell-queue-dead-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let queue = MmapQueue::<u64>::open(&path, 4).unwrap();
+        let dead = std::thread::spawn(current_tid).join().unwrap();
+
+        // a writer claimed position 0 and died before filling it in
+        queue.slot(0).word.store(word(0, dead), Ordering::Relaxed);
+        queue.slice.header().tail.value.store(1, Ordering::Relaxed);
+
+        queue.try_push(5).unwrap();
+        assert_eq!(queue.try_pop(), Some(5));
+
+        // a reader claimed position 2 and died before handing it back
+        queue.try_push(6).unwrap();
+        queue
+            .slot(2)
+            .word
+            .store(word(0, READING | dead), Ordering::Relaxed);
+        queue.slice.header().head.value.store(3, Ordering::Relaxed);
+
+        // the slot comes back around for the next lap
+        for i in 0..4 {
+            queue.try_push(i).unwrap();
+        }
+        assert_eq!(queue.try_push(9), Err(9));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
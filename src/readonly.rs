@@ -0,0 +1,208 @@
+use memmap2::Mmap;
+use std::{marker::PhantomData, ops::Deref, path::Path, time::Duration};
+
+use crate::{
+    check_layout, futex, lock::FileLock, LockMode, MmapCellError, MmapCellOptions, MmapPod,
+    SizePolicy,
+};
+
+/// A read-only [`MmapCell`](crate::MmapCell) backed by [`memmap2::Mmap`].
+///
+/// The file is only ever opened for reading so this works on files owned by
+/// another user, on read-only mounts and anywhere else a process can't write.
+///
+/// Since the mapping can't be written, `T` has to be [`MmapPod`]. An atomic
+/// would hand out stores that fault on the read-only pages:
+/// ```compile_fail
+/// # use mmapcell::MmapCellRef;
+/// # use std::sync::atomic::AtomicU32;
+/// let reader = MmapCellRef::<AtomicU32>::open_named_readonly("/tmp/mmapcellref-doc-test.bin");
+/// ```
+///
+/// # Example
+/// ```rust
+/// use mmapcell::{MmapCell, MmapCellRef};
+///
+/// let mut cell = MmapCell::<u64>::new_named("/tmp/mmapcellref-doc-test.bin").unwrap();
+/// *cell.get_mut() = 3;
+///
+/// let reader = MmapCellRef::<u64>::open_named_readonly("/tmp/mmapcellref-doc-test.bin").unwrap();
+///
+/// assert_eq!(*reader.get(), 3);
+/// ```
+pub struct MmapCellRef<T> {
+    raw: Mmap,
+    // dropped after raw so the file stays locked until it's unmapped
+    _lock: Option<FileLock>,
+    offset: usize,
+    trailing: usize,
+    _inner: PhantomData<T>,
+}
+
+impl<T> Deref for MmapCellRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.get()
+    }
+}
+
+impl<T: MmapPod> MmapCellRef<T> {
+    /// Wraps an existing read-only mapping, rejecting it unless it is exactly
+    /// `size_of::<T>()` bytes and aligned for `T`.
+    pub fn new(m: Mmap) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellRef::new_with_policy(m, SizePolicy::default())
+    }
+
+    /// Wraps an existing read-only mapping, rejecting it if it is smaller than
+    /// `size_of::<T>()` or not aligned for `T` and handling any extra
+    /// bytes according to `policy`.
+    pub fn new_with_policy(m: Mmap, policy: SizePolicy) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellRef::from_parts(m, 0, policy, None)
+    }
+
+    /// Opens an existing file read-only, rejecting it unless it is exactly
+    /// `size_of::<T>()` bytes.
+    pub fn open_named_readonly<P: AsRef<Path>>(path: P) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellOptions::new().open_readonly(path)
+    }
+
+    /// Opens an existing file read-only, rejecting it if it is smaller than
+    /// `size_of::<T>()` and handling any extra bytes according to `policy`.
+    pub fn open_named_readonly_with_policy<P: AsRef<Path>>(
+        path: P,
+        policy: SizePolicy,
+    ) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellOptions::new()
+            .size_policy(policy)
+            .open_readonly(path)
+    }
+
+    /// Opens an existing file read-only, rejecting it unless its [`Header`](crate::Header)
+    /// matches `T` and `fingerprint`.
+    pub fn open_named_readonly_with_header<P: AsRef<Path>>(
+        path: P,
+        fingerprint: u64,
+    ) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellOptions::new()
+            .header(fingerprint)
+            .open_readonly(path)
+    }
+
+    /// Like [`MmapCellRef::open_named_readonly`] but holds a `flock` on the file
+    /// until the cell is dropped, usually [`LockMode::Shared`].
+    ///
+    /// Fails with [`MmapCellError::Locked`] if a try-lock times out.
+    pub fn open_named_readonly_locked<P: AsRef<Path>>(
+        path: P,
+        mode: LockMode,
+    ) -> Result<MmapCellRef<T>, MmapCellError> {
+        MmapCellOptions::new().lock(mode).open_readonly(path)
+    }
+
+    pub(crate) fn from_parts(
+        m: Mmap,
+        offset: usize,
+        policy: SizePolicy,
+        lock: Option<FileLock>,
+    ) -> Result<MmapCellRef<T>, MmapCellError> {
+        let trailing = check_layout::<T>(&m, offset, policy)?;
+
+        Ok(MmapCellRef {
+            raw: m,
+            _lock: lock,
+            offset,
+            trailing,
+            _inner: PhantomData,
+        })
+    }
+}
+
+impl<T> MmapCellRef<T> {
+    /// The mapped `T`, borrowed for as long as the cell.
+    pub fn get(&self) -> &T {
+        // SAFETY: size and alignment were checked on construction and the constructors
+        // only accept MmapPod types, which a shared reference can't write to
+        unsafe {
+            self.raw
+                .as_ptr()
+                .add(self.offset)
+                .cast::<T>()
+                .as_ref()
+                .expect("not null pointer")
+        }
+    }
+
+    /// The bytes past `size_of::<T>()`.
+    ///
+    /// Always empty unless the cell was created with [`SizePolicy::ExposeTrailing`].
+    pub fn trailing(&self) -> &[u8] {
+        let start = self.offset + size_of::<T>();
+        &self.raw[start..start + self.trailing]
+    }
+
+    /// Sleeps for as long as the `u32` picked out by `field` holds `expected`,
+    /// see [`MmapCell::wait_while`](crate::MmapCell::wait_while).
+    ///
+    /// Waiting only needs read access, so a read-only consumer can still be
+    /// woken by a writer in another process that maps the same bytes as an
+    /// `AtomicU32` and calls [`notify_all`](crate::MmapCell::notify_all).
+    pub fn wait_while<F>(&self, field: F, expected: u32, timeout: Option<Duration>) -> bool
+    where
+        F: FnOnce(&T) -> &u32,
+    {
+        // SAFETY: a reference is aligned and valid for reads while we hold it
+        unsafe { futex::wait_while_readonly(field(self.get()), expected, timeout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn readonly_header() {
+        let path = std::env::temp_dir().join("mmapcellref-header-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut cell = MmapCell::<[u32; 2]>::new_named_with_header(&path, 7).unwrap();
+        *cell.get_mut() = [1, 2];
+        drop(cell);
+
+        let reader = MmapCellRef::<[u32; 2]>::open_named_readonly_with_header(&path, 7).unwrap();
+        assert_eq!(*reader, [1, 2]);
+
+        assert!(MmapCellRef::<[u32; 2]>::open_named_readonly_with_header(&path, 8).is_err());
+
+        drop(reader);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn wait_on_readonly_mapping() {
+        let path = std::env::temp_dir().join("mmapcellref-wait-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let writer = MmapCell::<AtomicU32>::new_named(&path).unwrap();
+
+        // a file opened without write access can only be mapped PROT_READ
+        let file = std::fs::File::open(&path).unwrap();
+        let reader = MmapCellRef::<u32>::new(unsafe { Mmap::map(&file).unwrap() }).unwrap();
+        assert_eq!(*reader, 0);
+
+        let waker = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            writer.store(5, Ordering::Release);
+            writer.notify_all(|v| v);
+        });
+
+        assert!(reader.wait_while(|v| v, 0, Some(Duration::from_secs(5))));
+        assert_eq!(*reader, 5);
+        assert!(!reader.wait_while(|v| v, 5, Some(Duration::from_millis(10))));
+
+        waker.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, LayoutError, MmapCellError, MmapHeaderSlice, MmapSafe};
+
+/// An index the other side polls, on a cache line of its own so the
+/// producer and consumer don't keep stealing it from each other.
+#[repr(C, align(64))]
+pub(crate) struct CachePadded {
+    pub(crate) value: AtomicU32,
+    /// set while the other side is (about to be) asleep on `value`
+    pub(crate) waiting: AtomicU32,
+    _pad: [u32; 14],
+}
+
+// SAFETY: 16 plain words filling the whole cache line
+unsafe impl MmapSafe for CachePadded {}
+
+#[repr(C)]
+struct RingHeader {
+    capacity: u32,
+    _pad: [u32; 15],
+    /// next position the producer writes, only ever moved by the producer
+    tail: CachePadded,
+    /// next position the consumer reads, only ever moved by the consumer
+    head: CachePadded,
+}
+
+// SAFETY: a whole cache line of plain words and then two more,
+// any capacity that isn't a power of two is rejected on open
+unsafe impl MmapSafe for RingHeader {}
+
+/// A single producer, single consumer bounded queue in a file.
+///
+/// The producer and the consumer each open the file on their own (usually from
+/// different processes). The head and tail indices live in the file too, so a
+/// consumer that restarts carries on exactly where it left off.
+///
+/// Nothing stops two producers (or two consumers) from using the same file
+/// at once, but the queue falls apart if they do.
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapRing;
+///
+/// let path = "/tmp/mmapcell-ring-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+///
+/// let mut producer = MmapRing::<u64>::open(path, 1024).unwrap();
+/// let mut consumer = MmapRing::<u64>::open(path, 1024).unwrap();
+///
+/// producer.try_push(7).unwrap();
+/// assert_eq!(producer.try_push_slice(&[8, 9]), 2);
+///
+/// let mut batch = [0; 4];
+/// assert_eq!(consumer.try_pop(), Some(7));
+/// assert_eq!(consumer.try_pop_slice(&mut batch), 2);
+/// assert_eq!(batch[..2], [8, 9]);
+/// ```
+pub struct MmapRing<T> {
+    slice: MmapHeaderSlice<RingHeader, T>,
+}
+
+impl<T: MmapSafe> MmapRing<T> {
+    /// Opens the ring at `path`, creating an empty one with room for
+    /// `capacity` elements if the file doesn't exist yet.
+    ///
+    /// `capacity` has to be a power of two and match the existing ring, if any.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: u32) -> Result<MmapRing<T>, MmapCellError> {
+        if !capacity.is_power_of_two() || capacity > 1 << 31 {
+            return Err(LayoutError::BadCapacity {
+                capacity: capacity as usize,
+            }
+            .into());
+        }
+
+        let path = path.as_ref();
+        let count = |h: &RingHeader| h.capacity as usize;
+
+        let header = RingHeader {
+            capacity,
+            _pad: [0; 15],
+            tail: CachePadded::default(),
+            head: CachePadded::default(),
+        };
+
+        let slice = MmapHeaderSlice::open_or_create(path, header, count)?;
+
+        if slice.header().capacity != capacity {
+            return Err(LayoutError::BadCapacity {
+                capacity: slice.header().capacity as usize,
+            }
+            .into());
+        }
+
+        Ok(MmapRing { slice })
+    }
+
+    /// Appends `value`, or hands it back if the ring is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        let header = self.slice.header();
+        let mask = header.capacity - 1;
+
+        let tail = header.tail.value.load(Ordering::Relaxed);
+        let head = header.head.value.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) > mask {
+            return Err(value);
+        }
+
+        // SAFETY: the consumer is done with every slot from head up to tail + capacity
+        unsafe { self.slot(tail).write(value) };
+
+        publish(&header.tail, tail.wrapping_add(1));
+
+        Ok(())
+    }
+
+    /// Removes the oldest element, if there is one.
+    pub fn try_pop(&mut self) -> Option<T> {
+        let header = self.slice.header();
+
+        let head = header.head.value.load(Ordering::Relaxed);
+        let tail = header.tail.value.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: the producer is done with every slot from head up to tail
+        let value = unsafe { self.slot(head).read() };
+
+        publish(&header.head, head.wrapping_add(1));
+
+        Some(value)
+    }
+
+    /// Like [`try_push`](MmapRing::try_push) but waits for room for up to `timeout`,
+    /// or forever if it's `None`.
+    pub fn push(&mut self, mut value: T, timeout: Option<Duration>) -> Result<(), T> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            // read before trying, so a pop in between makes the wait return right away
+            let head = self.slice.header().head.value.load(Ordering::SeqCst);
+
+            value = match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(value) => value,
+            };
+
+            if !wait_for_change(&self.slice.header().head, head, deadline) {
+                return Err(value);
+            }
+        }
+    }
+
+    /// Like [`try_pop`](MmapRing::try_pop) but waits for an element for up to
+    /// `timeout`, or forever if it's `None`.
+    pub fn pop(&mut self, timeout: Option<Duration>) -> Option<T> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            let tail = self.slice.header().tail.value.load(Ordering::SeqCst);
+
+            if let Some(value) = self.try_pop() {
+                return Some(value);
+            }
+
+            if !wait_for_change(&self.slice.header().tail, tail, deadline) {
+                return None;
+            }
+        }
+    }
+}
+
+impl<T: MmapSafe + Copy> MmapRing<T> {
+    /// Appends as many of `values` as fit, returning how many that was.
+    pub fn try_push_slice(&mut self, values: &[T]) -> usize {
+        let header = self.slice.header();
+
+        let tail = header.tail.value.load(Ordering::Relaxed);
+        let head = header.head.value.load(Ordering::Acquire);
+
+        let free = header.capacity - tail.wrapping_sub(head);
+        let n = values.len().min(free as usize);
+
+        for (i, value) in values[..n].iter().enumerate() {
+            // SAFETY: see try_push
+            unsafe { self.slot(tail.wrapping_add(i as u32)).write(*value) };
+        }
+
+        if n > 0 {
+            publish(&header.tail, tail.wrapping_add(n as u32));
+        }
+
+        n
+    }
+
+    /// Removes as many elements as fit in `out`, returning how many that was.
+    pub fn try_pop_slice(&mut self, out: &mut [T]) -> usize {
+        let header = self.slice.header();
+
+        let head = header.head.value.load(Ordering::Relaxed);
+        let tail = header.tail.value.load(Ordering::Acquire);
+
+        let n = out.len().min(tail.wrapping_sub(head) as usize);
+
+        for (i, out) in out[..n].iter_mut().enumerate() {
+            // SAFETY: see try_pop
+            *out = unsafe { self.slot(head.wrapping_add(i as u32)).read() };
+        }
+
+        if n > 0 {
+            publish(&header.head, head.wrapping_add(n as u32));
+        }
+
+        n
+    }
+}
+
+impl<T> MmapRing<T> {
+    /// The slot `position` lands on. The other side reads and writes slots too,
+    /// so they're only ever touched through raw pointers and never borrowed.
+    fn slot(&self, position: u32) -> *mut T {
+        let mask = self.slice.header().capacity - 1;
+
+        // SAFETY: the capacity was checked against the file on open
+        unsafe { self.slice.entries_ptr().add((position & mask) as usize) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slice.header().capacity as usize
+    }
+
+    /// How many elements are queued right now.
+    pub fn len(&self) -> usize {
+        let header = self.slice.header();
+
+        let tail = header.tail.value.load(Ordering::Acquire);
+        let head = header.head.value.load(Ordering::Acquire);
+
+        tail.wrapping_sub(head) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for CachePadded {
+    fn default() -> CachePadded {
+        CachePadded {
+            value: AtomicU32::new(0),
+            waiting: AtomicU32::new(0),
+            _pad: [0; 14],
+        }
+    }
+}
+
+/// Moves an index forward and wakes the other side if it's asleep on it.
+pub(crate) fn publish(index: &CachePadded, value: u32) {
+    index.value.store(value, Ordering::SeqCst);
+
+    if index.waiting.load(Ordering::SeqCst) != 0 {
+        futex::wake_all(&index.value);
+    }
+}
+
+/// Sleeps until `index` moves past `seen`, returns `false` if the deadline passed first.
+pub(crate) fn wait_for_change(index: &CachePadded, seen: u32, deadline: Option<Instant>) -> bool {
+    let timeout = match deadline {
+        None => None,
+        Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+            left if left.is_zero() => return false,
+            left => Some(left),
+        },
+    };
+
+    index.waiting.store(1, Ordering::SeqCst);
+
+    if index.value.load(Ordering::SeqCst) == seen {
+        futex::wait(&index.value, seen, timeout);
+    }
+
+    index.waiting.store(0, Ordering::Relaxed);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_across_mappings() {
+        let path = std::env::temp_dir().join("mmapcell-ring-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(MmapRing::<u64>::open(&path, 3).is_err());
+
+        // both sides race to create the file
+        let consumer = std::thread::spawn({
+            let path = path.clone();
+
+            move || {
+                let mut consumer = MmapRing::<u64>::open(&path, 8).unwrap();
+                let mut sum = 0;
+
+                for _ in 0..500 {
+                    sum += consumer.pop(Some(Duration::from_secs(5))).unwrap();
+
+                    // restart the consumer halfway through
+                    if sum == (0..250).sum() {
+                        consumer = MmapRing::<u64>::open(&path, 8).unwrap();
+                    }
+                }
+
+                sum
+            }
+        });
+
+        let mut producer = MmapRing::<u64>::open(&path, 8).unwrap();
+
+        for i in 0..500 {
+            producer.push(i, None).unwrap();
+        }
+
+        assert_eq!(consumer.join().unwrap(), (0..500).sum());
+        assert!(producer.is_empty());
+        assert!(MmapRing::<u64>::open(&path, 16).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn blocking_without_timeout() {
+        let path = std::env::temp_dir().join("mmapcell-ring-blocking-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut producer = MmapRing::<u32>::open(&path, 1).unwrap();
+
+        // a single slot keeps both sides going to sleep on each other,
+        // a single lost wakeup hangs this test for good
+        let consumer = std::thread::spawn({
+            let path = path.clone();
+
+            move || {
+                let mut consumer = MmapRing::<u32>::open(&path, 1).unwrap();
+                (0..100_000)
+                    .map(|_| consumer.pop(None).unwrap())
+                    .sum::<u32>()
+            }
+        });
+
+        for i in 0..100_000 {
+            producer.push(i % 3, None).unwrap();
+        }
+
+        assert_eq!(
+            consumer.join().unwrap(),
+            (0..100_000).map(|i| i % 3).sum::<u32>()
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
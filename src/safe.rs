@@ -0,0 +1,112 @@
+use std::marker::PhantomData;
+use std::sync::atomic::{
+    AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16, AtomicU32, AtomicU64,
+    AtomicU8, AtomicUsize,
+};
+
+/// Types that can live directly in mapped memory.
+///
+/// Anything that is `MmapSafe` can be handed out by the safe [`MmapCell`](crate::MmapCell)
+/// constructors, since whatever bytes happen to be in the file (including the zeroes
+/// of a freshly created one) are a valid value.
+///
+/// Prefer `#[derive(MmapSafe)]` which checks all of the below at compile time.
+///
+/// ```compile_fail
+/// # use mmapcell::MmapSafe;
+/// // padding between the two fields
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Padded {
+///     a: u8,
+///     b: u32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use mmapcell::MmapSafe;
+/// // a pointer means nothing to the next process that maps the file
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Pointer {
+///     p: &'static u64,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use mmapcell::MmapSafe;
+/// // no stable layout
+/// #[derive(MmapSafe)]
+/// struct Rusty {
+///     a: u64,
+/// }
+/// ```
+///
+/// # Safety
+/// implementors must be plain old data:
+/// - a stable layout, `#[repr(C)]` or `#[repr(transparent)]`
+/// - no pointers, references or anything else that only means something inside one process
+/// - every bit pattern is a valid value
+/// - no padding bytes
+pub unsafe trait MmapSafe {}
+
+/// [`MmapSafe`] types without any interior mutability, so nothing can change
+/// them through a shared reference.
+///
+/// Needed wherever the crate hands out `&T` or its bytes while the memory
+/// itself may not be written, like [`MmapCell::as_bytes`](crate::MmapCell::as_bytes)
+/// or the read-only mappings of [`MmapCellRef`](crate::MmapCellRef).
+/// Atomics and the [`sync`](crate::sync) primitives are `MmapSafe` but not `MmapPod`.
+///
+/// Prefer `#[derive(MmapPod)]` which checks every field is `MmapPod` at compile time.
+///
+/// ```compile_fail
+/// # use mmapcell::{MmapPod, MmapSafe};
+/// # use std::sync::atomic::AtomicU32;
+/// #[derive(MmapSafe, MmapPod)]
+/// #[repr(C)]
+/// struct Counter {
+///     count: AtomicU32,
+/// }
+/// ```
+///
+/// # Safety
+/// implementors must not contain an [`UnsafeCell`](std::cell::UnsafeCell),
+/// directly or through any of their fields
+pub unsafe trait MmapPod: MmapSafe {}
+
+macro_rules! impl_mmap_safe {
+    ($($t:ty),* $(,)?) => {
+        $(unsafe impl MmapSafe for $t {})*
+    };
+}
+
+macro_rules! impl_mmap_pod {
+    ($($t:ty),* $(,)?) => {
+        $(unsafe impl MmapPod for $t {})*
+    };
+}
+
+impl_mmap_safe! {
+    (),
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    f32, f64,
+    AtomicU8, AtomicU16, AtomicU32, AtomicU64, AtomicUsize,
+    AtomicI8, AtomicI16, AtomicI32, AtomicI64, AtomicIsize,
+}
+
+impl_mmap_pod! {
+    (),
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    f32, f64,
+}
+
+unsafe impl<T: MmapSafe, const N: usize> MmapSafe for [T; N] {}
+
+unsafe impl<T: MmapPod, const N: usize> MmapPod for [T; N] {}
+
+unsafe impl<T: ?Sized> MmapSafe for PhantomData<T> {}
+
+unsafe impl<T: ?Sized> MmapPod for PhantomData<T> {}
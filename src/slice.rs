@@ -0,0 +1,305 @@
+use memmap2::MmapMut;
+use std::{
+    ops::{Deref, DerefMut},
+    path::Path,
+};
+
+use crate::{LayoutError, MmapCell, MmapCellError, MmapCellOptions, MmapSafe, SizePolicy};
+
+/// A mapping of a whole file as a slice of `U`, with the length taken from the file size.
+///
+/// [`MmapCell<[U; N]>`](crate::MmapCell) needs `N` at compile time, this picks
+/// it up from `file_len / size_of::<U>()` when opening and takes it as an
+/// argument when creating.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{MmapSafe, MmapSlice};
+///
+/// #[derive(MmapSafe, Clone, Copy)]
+/// #[repr(C)]
+/// struct Record {
+///     id: u32,
+///     score: f32,
+/// }
+///
+/// let path = "/tmp/mmapcell-slice-doc-test.bin";
+///
+/// let mut records = MmapSlice::<Record>::new_named(path, 128).unwrap();
+/// records[3].score = 1.5;
+/// drop(records);
+///
+/// let records = MmapSlice::<Record>::open_named(path).unwrap();
+/// assert_eq!(records.len(), 128);
+/// assert_eq!(records.iter().map(|r| r.score).sum::<f32>(), 1.5);
+/// # }
+/// ```
+pub struct MmapSlice<U> {
+    /// nothing but trailing bytes, the empty array just carries the alignment
+    cell: MmapCell<[U; 0]>,
+    len: usize,
+}
+
+impl<U> Deref for MmapSlice<U> {
+    type Target = [U];
+
+    fn deref(&self) -> &[U] {
+        self.get()
+    }
+}
+
+impl<U> DerefMut for MmapSlice<U> {
+    fn deref_mut(&mut self) -> &mut [U] {
+        self.get_mut()
+    }
+}
+
+impl<'a, U> IntoIterator for &'a MmapSlice<U> {
+    type Item = &'a U;
+    type IntoIter = std::slice::Iter<'a, U>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.get().iter()
+    }
+}
+
+impl<'a, U> IntoIterator for &'a mut MmapSlice<U> {
+    type Item = &'a mut U;
+    type IntoIter = std::slice::IterMut<'a, U>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.get_mut().iter_mut()
+    }
+}
+
+/// How many `U` fit in `bytes`, checking alignment and
+/// handling a partial element at the end according to `policy`.
+fn check_slice_layout<U>(bytes: &[u8], policy: SizePolicy) -> Result<usize, LayoutError> {
+    let len = bytes.len() / size_of::<U>();
+
+    // a partial element can't be exposed, so it's only ever allowed or not
+    if policy == SizePolicy::Strict && len * size_of::<U>() != bytes.len() {
+        return Err(LayoutError::TrailingBytes {
+            expected: len * size_of::<U>(),
+            actual: bytes.len(),
+        });
+    }
+
+    let addr = bytes.as_ptr() as usize;
+    if !addr.is_multiple_of(align_of::<U>()) {
+        return Err(LayoutError::Misaligned {
+            align: align_of::<U>(),
+            addr,
+        });
+    }
+
+    Ok(len)
+}
+
+impl<U: MmapSafe> MmapSlice<U> {
+    /// Wraps an existing mapping, rejecting it unless it is a whole
+    /// number of `U` and aligned for `U`.
+    pub fn new(m: MmapMut) -> Result<MmapSlice<U>, MmapCellError> {
+        MmapSlice::new_with_policy(m, SizePolicy::default())
+    }
+
+    /// Wraps an existing mapping, ignoring a partial `U` at the end
+    /// unless `policy` is [`SizePolicy::Strict`].
+    pub fn new_with_policy(m: MmapMut, policy: SizePolicy) -> Result<MmapSlice<U>, MmapCellError> {
+        let cell = MmapCell::new_with_policy(m, SizePolicy::ExposeTrailing)?;
+
+        MmapSlice::from_cell(cell, policy)
+    }
+
+    /// A zeroed anonymous mapping of `len` elements.
+    pub fn new_anon(len: usize) -> Result<MmapSlice<U>, MmapCellError> {
+        let m = MmapMut::map_anon(Self::byte_len(len)?)?;
+
+        MmapSlice::new(m)
+    }
+
+    /// Opens (or creates) the file at `path` and sets it to exactly `len` elements,
+    /// zero filling any that are new.
+    pub fn new_named<P: AsRef<Path>>(path: P, len: usize) -> Result<MmapSlice<U>, MmapCellError> {
+        let options = MmapCell::options().create(true).resize(true);
+
+        MmapSlice::open_with_options(options, path, len)
+    }
+
+    /// Opens an existing file at `path` as however many elements it holds.
+    pub fn open_named<P: AsRef<Path>>(path: P) -> Result<MmapSlice<U>, MmapCellError> {
+        MmapSlice::open_named_with_policy(path, SizePolicy::default())
+    }
+
+    /// Like [`MmapSlice::open_named`] but ignoring a partial `U` at the end
+    /// unless `policy` is [`SizePolicy::Strict`].
+    pub fn open_named_with_policy<P: AsRef<Path>>(
+        path: P,
+        policy: SizePolicy,
+    ) -> Result<MmapSlice<U>, MmapCellError> {
+        MmapSlice::open_with_options(MmapCell::options().size_policy(policy), path, 0)
+    }
+
+    /// Opens the file at `path` the way `options` says, say with a
+    /// [`lock`](MmapCellOptions::lock) or a [`header`](MmapCellOptions::header).
+    ///
+    /// A file it creates or resizes is set to exactly `len` elements, otherwise the
+    /// slice is however many the file holds. A partial `U` at the end is ignored
+    /// unless the size policy is [`SizePolicy::Strict`], and the initializer doesn't apply.
+    ///
+    /// A header records the size and alignment of `U`, the fingerprint
+    /// has to tell apart element types that share both.
+    pub fn open_with_options<P: AsRef<Path>>(
+        options: MmapCellOptions<U>,
+        path: P,
+        len: usize,
+    ) -> Result<MmapSlice<U>, MmapCellError> {
+        let policy = options.get_size_policy();
+
+        let cell = options
+            .retype::<[U; 0]>()
+            .describe::<U>()
+            .size_policy(SizePolicy::ExposeTrailing)
+            .trailing(Self::byte_len(len)?)
+            .open(path)?;
+
+        MmapSlice::from_cell(cell, policy)
+    }
+
+    fn from_cell(
+        cell: MmapCell<[U; 0]>,
+        policy: SizePolicy,
+    ) -> Result<MmapSlice<U>, MmapCellError> {
+        const { assert!(size_of::<U>() != 0, "MmapSlice can't hold zero sized types") };
+
+        let len = check_slice_layout::<U>(cell.trailing(), policy)?;
+
+        Ok(MmapSlice { cell, len })
+    }
+
+    fn byte_len(len: usize) -> Result<usize, std::io::Error> {
+        len.checked_mul(size_of::<U>()).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::OutOfMemory, "slice length overflow")
+        })
+    }
+}
+
+impl<U> MmapSlice<U> {
+    /// How many elements are mapped.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The mapped elements, borrowed for as long as the slice.
+    pub fn get(&self) -> &[U] {
+        // SAFETY: size and alignment were checked on construction and
+        // the constructors only accept MmapSafe types
+        unsafe { std::slice::from_raw_parts(self.cell.trailing_ptr().cast::<U>(), self.len) }
+    }
+
+    /// The mapped elements, mutably borrowed for as long as the slice.
+    pub fn get_mut(&mut self) -> &mut [U] {
+        unsafe { std::slice::from_raw_parts_mut(self.cell.trailing_ptr().cast::<U>(), self.len) }
+    }
+
+    /// Writes dirty pages back to the file and waits for it to finish.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.cell.flush()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, U> {
+        self.get().iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, U> {
+        self.get_mut().iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_from_file_size() {
+        let path = std::env::temp_dir().join("mmapcell-slice-test.bin");
+        std::fs::write(&path, [1u8; 10]).unwrap();
+
+        assert!(matches!(
+            MmapSlice::<u32>::open_named(&path),
+            Err(MmapCellError::Layout(LayoutError::TrailingBytes {
+                expected: 8,
+                actual: 10
+            }))
+        ));
+
+        let slice =
+            MmapSlice::<u32>::open_named_with_policy(&path, SizePolicy::AllowTrailing).unwrap();
+        assert_eq!(slice.len(), 2);
+        drop(slice);
+
+        let mut slice = MmapSlice::<u32>::new_named(&path, 4).unwrap();
+        assert_eq!(slice.len(), 4);
+        assert_eq!(slice[0], 0x01010101);
+        assert_eq!(slice[3], 0);
+
+        for (i, v) in slice.iter_mut().enumerate() {
+            *v = i as u32;
+        }
+        drop(slice);
+
+        let slice = MmapSlice::<u32>::open_named(&path).unwrap();
+        assert_eq!(slice.iter().copied().collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn slice_with_options() {
+        use crate::HeaderError;
+
+        let path = std::env::temp_dir().join("mmapcell-slice-options-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let options = || MmapCell::<u32>::options().create(true).header(3);
+
+        let mut slice = MmapSlice::open_with_options(options(), &path, 4).unwrap();
+        slice[3] = 7;
+        drop(slice);
+
+        // the header comes first and isn't mistaken for elements
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 40 + 4 * 4);
+
+        // an existing file keeps however many it holds unless it's resized
+        let slice = MmapSlice::open_with_options(options(), &path, 8).unwrap();
+        assert_eq!(slice.get(), &[0, 0, 0, 7]);
+        drop(slice);
+
+        let slice = MmapSlice::open_with_options(options().resize(true), &path, 2).unwrap();
+        assert_eq!(slice.get(), &[0, 0]);
+        drop(slice);
+
+        assert!(matches!(
+            MmapSlice::open_with_options(options().header(4), &path, 2),
+            Err(MmapCellError::Header(
+                HeaderError::FingerprintMismatch { .. }
+            ))
+        ));
+
+        // the header records the element type, not the empty array standing in for it
+        assert!(matches!(
+            MmapSlice::open_with_options(MmapCell::<[u32; 2]>::options().header(3), &path, 1),
+            Err(MmapCellError::Header(HeaderError::SizeMismatch {
+                expected: 8,
+                found: 4
+            }))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, MmapSafe};
+
+use super::TimedOut;
+
+/// the low half of the state word counts arrivals,
+/// the high half is bumped every time the barrier opens
+const ARRIVED: u32 = 0xffff;
+const GENERATION: u32 = 1 << 16;
+
+/// A barrier that lives inside the mapping itself, for processes that need to
+/// rendezvous at phase boundaries.
+///
+/// Unlike the locks in this module there is no owner to check on, a
+/// participant that dies before arriving simply never shows up. So there's
+/// only [`wait_timeout`](ShmBarrier::wait_timeout), which gives up with
+/// [`TimedOut`] instead of waiting forever and takes back its own arrival
+/// so the barrier is left as if it never came.
+///
+/// At most 65535 participants are supported. A zeroed barrier has no
+/// participants and never blocks, so give it its real count through an
+/// initializer like [`MmapCell::open_or_create`](crate::MmapCell::open_or_create).
+///
+/// # Example
+/// ```rust
+/// use mmapcell::{sync::ShmBarrier, MmapCell};
+/// use std::time::Duration;
+///
+/// let path = "/tmp/mmapcell-barrier-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let cell = MmapCell::<ShmBarrier>::open_or_create(path, || ShmBarrier::new(4)).unwrap();
+///
+///         std::thread::spawn(move || cell.wait_timeout(Duration::from_secs(10)).unwrap())
+///     })
+///     .collect();
+///
+/// let leaders = workers
+///     .into_iter()
+///     .map(|w| w.join().unwrap())
+///     .filter(|result| result.is_leader())
+///     .count();
+///
+/// assert_eq!(leaders, 1);
+/// ```
+#[repr(C)]
+pub struct ShmBarrier {
+    parties: AtomicU32,
+    state: AtomicU32,
+}
+
+// SAFETY: any party count and state word is handled
+unsafe impl MmapSafe for ShmBarrier {}
+
+/// Returned by every participant once the barrier opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Whether this was the last participant to arrive, exactly one is.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl ShmBarrier {
+    /// # Panics
+    /// if `parties` is more than 65535
+    pub const fn new(parties: u32) -> ShmBarrier {
+        assert!(
+            parties <= ARRIVED,
+            "ShmBarrier supports at most 65535 parties"
+        );
+
+        ShmBarrier {
+            parties: AtomicU32::new(parties),
+            state: AtomicU32::new(0),
+        }
+    }
+
+    pub fn parties(&self) -> u32 {
+        self.parties.load(Ordering::Relaxed).min(ARRIVED)
+    }
+
+    /// Blocks until every participant has arrived, or gives up after `timeout`.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<BarrierWaitResult, TimedOut> {
+        let deadline = Instant::now() + timeout;
+        let parties = self.parties();
+        let mut state = self.state.load(Ordering::Acquire);
+
+        let generation = loop {
+            let generation = state & !ARRIVED;
+            let arrived = (state & ARRIVED) + 1;
+
+            let (new, leader) = match arrived >= parties {
+                true => (generation.wrapping_add(GENERATION), true),
+                false => (state + 1, false),
+            };
+
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) if leader => {
+                    futex::wake_all(&self.state);
+                    return Ok(BarrierWaitResult(true));
+                }
+                Ok(_) => break generation,
+                Err(actual) => state = actual,
+            }
+        };
+
+        loop {
+            let state = self.state.load(Ordering::Acquire);
+
+            if state & !ARRIVED != generation {
+                return Ok(BarrierWaitResult(false));
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return self.withdraw(generation);
+            }
+
+            futex::wait(&self.state, state, Some(left));
+        }
+    }
+
+    /// Takes back an arrival after timing out, unless the barrier opened in the meantime.
+    fn withdraw(&self, generation: u32) -> Result<BarrierWaitResult, TimedOut> {
+        let mut state = self.state.load(Ordering::Acquire);
+
+        while state & !ARRIVED == generation {
+            match self
+                .state
+                .compare_exchange(state, state - 1, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Err(TimedOut),
+                Err(actual) => state = actual,
+            }
+        }
+
+        Ok(BarrierWaitResult(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+
+    #[test]
+    fn barrier_timeout() {
+        let path = std::env::temp_dir().join("mmapcell-barrier-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let cell = MmapCell::<ShmBarrier>::open_or_create(&path, || ShmBarrier::new(3)).unwrap();
+        assert_eq!(cell.parties(), 3);
+
+        // the third participant "died", so the two that showed up time out
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let cell = MmapCell::<ShmBarrier>::open_named(&path).unwrap();
+                std::thread::spawn(move || cell.wait_timeout(Duration::from_millis(50)))
+            })
+            .collect();
+
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), Err(TimedOut));
+        }
+
+        // and the arrivals they took back don't count towards the next round
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let cell = MmapCell::<ShmBarrier>::open_named(&path).unwrap();
+                std::thread::spawn(move || cell.wait_timeout(Duration::from_secs(5)).unwrap())
+            })
+            .collect();
+
+        let leaders = waiters
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .filter(|result| result.is_leader())
+            .count();
+
+        assert_eq!(leaders, 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
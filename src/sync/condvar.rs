@@ -0,0 +1,170 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, MmapSafe};
+
+use super::{LockResult, OwnerDied, ShmMutexGuard};
+
+/// A condition variable that lives inside the mapping itself, to be used
+/// with a [`ShmMutex`](super::ShmMutex) from the same (or any other) mapping.
+///
+/// An all zero `ShmCondvar` is ready to use. Like any condition variable,
+/// waits can wake up spuriously so always check the condition in a loop
+/// (or use [`wait_while`](ShmCondvar::wait_while)).
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{sync::{ShmCondvar, ShmMutex}, MmapCell, MmapSafe};
+///
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Phase {
+///     loaded: ShmMutex<u32>,
+///     changed: ShmCondvar,
+/// }
+///
+/// let path = "/tmp/mmapcell-condvar-doc-test.bin";
+/// let coordinator = MmapCell::<Phase>::new_named(path).unwrap();
+/// *coordinator.loaded.lock().unwrap() = 0;
+///
+/// let workers: Vec<_> = (0..3)
+///     .map(|_| {
+///         let worker = MmapCell::<Phase>::open_named(path).unwrap();
+///
+///         std::thread::spawn(move || {
+///             *worker.loaded.lock().unwrap() += 1;
+///             worker.changed.notify_all();
+///         })
+///     })
+///     .collect();
+///
+/// let loaded = coordinator
+///     .changed
+///     .wait_while(coordinator.loaded.lock().unwrap(), |loaded| *loaded < 3)
+///     .unwrap();
+///
+/// assert_eq!(*loaded, 3);
+/// # drop(loaded);
+/// # workers.into_iter().for_each(|w| w.join().unwrap());
+/// # }
+/// ```
+#[repr(C)]
+pub struct ShmCondvar {
+    /// bumped on every notify, it's what waiters sleep on
+    epoch: AtomicU32,
+    sleepers: AtomicU32,
+}
+
+// SAFETY: two counters, any value is fine
+unsafe impl MmapSafe for ShmCondvar {}
+
+/// Whether a [`ShmCondvar::wait_timeout`] returned because of the timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl ShmCondvar {
+    pub const fn new() -> ShmCondvar {
+        ShmCondvar {
+            epoch: AtomicU32::new(0),
+            sleepers: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlocks the mutex, sleeps until notified and locks it again.
+    pub fn wait<'a, T>(&self, guard: ShmMutexGuard<'a, T>) -> LockResult<ShmMutexGuard<'a, T>> {
+        let mutex = guard.mutex;
+        let epoch = self.epoch.load(Ordering::SeqCst);
+
+        drop(guard);
+        self.sleep(epoch, None);
+
+        mutex.lock()
+    }
+
+    /// Like [`wait`](ShmCondvar::wait) but only sleeps for up to `timeout`.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: ShmMutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> LockResult<(ShmMutexGuard<'a, T>, WaitTimeoutResult)> {
+        let mutex = guard.mutex;
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+
+        drop(guard);
+        self.sleep(epoch, Some(timeout));
+
+        let timed_out = WaitTimeoutResult(Instant::now() >= deadline);
+
+        match mutex.lock() {
+            Ok(guard) => Ok((guard, timed_out)),
+            Err(died) => Err(OwnerDied::new((died.into_inner(), timed_out))),
+        }
+    }
+
+    /// Waits for as long as `condition` holds, checking it again after every wakeup.
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: ShmMutexGuard<'a, T>,
+        mut condition: F,
+    ) -> LockResult<ShmMutexGuard<'a, T>>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut died = false;
+
+        while condition(&mut guard) {
+            guard = match self.wait(guard) {
+                Ok(guard) => guard,
+                Err(e) => {
+                    died = true;
+                    e.into_inner()
+                }
+            };
+        }
+
+        match died {
+            true => Err(OwnerDied::new(guard)),
+            false => Ok(guard),
+        }
+    }
+
+    /// Wakes one waiter, in whichever process it is.
+    pub fn notify_one(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            futex::wake_one(&self.epoch);
+        }
+    }
+
+    /// Wakes every waiter, in whichever process they are.
+    pub fn notify_all(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            futex::wake_all(&self.epoch);
+        }
+    }
+
+    fn sleep(&self, epoch: u32, timeout: Option<Duration>) {
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        futex::wait(&self.epoch, epoch, timeout);
+        self.sleepers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for ShmCondvar {
+    fn default() -> ShmCondvar {
+        ShmCondvar::new()
+    }
+}
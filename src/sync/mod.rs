@@ -0,0 +1,150 @@
+//! Cross-process synchronization primitives that live inside mapped memory.
+//!
+//! Everything here is built on shared futexes and records owners by thread id,
+//! so a process (or thread) that dies while holding something is noticed by the
+//! next one to come along. That only works between processes that share a pid
+//! namespace, and like any pid based scheme can in principle be fooled by an id
+//! being reused in between.
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+mod barrier;
+mod condvar;
+mod mutex;
+mod once;
+mod rwlock;
+mod semaphore;
+mod seqlock;
+
+pub use barrier::{BarrierWaitResult, ShmBarrier};
+pub use condvar::{ShmCondvar, WaitTimeoutResult};
+pub use mutex::{ShmMutex, ShmMutexGuard};
+pub use once::SharedOnce;
+pub use rwlock::{RwLockPolicy, ShmRwLock, ShmRwLockReadGuard, ShmRwLockWriteGuard, READER_SLOTS};
+pub use semaphore::{ShmSemaphore, ShmSemaphorePermit, HOLDER_SLOTS};
+pub use seqlock::ShmSeqLock;
+
+/// How long to sleep at a time while waiting on something held by
+/// another thread, before checking whether it's still alive.
+pub(crate) const OWNER_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The kernel thread id of the calling thread.
+pub(crate) fn current_tid() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+/// Whether a thread (or process) with this id still exists.
+pub(crate) fn is_alive(id: u32) -> bool {
+    let Ok(id) = libc::pid_t::try_from(id) else {
+        return false;
+    };
+
+    if unsafe { libc::kill(id, 0) } == 0 {
+        return true;
+    }
+
+    // EPERM means it exists, it just belongs to somebody else
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Returned instead of a guard when the previous holder died while holding it.
+///
+/// Whatever it was protecting may have been left half updated. The guard is
+/// still handed over so the new holder can repair it, and once this is dropped
+/// (or unwrapped) the lock carries on as normal.
+pub struct OwnerDied<G> {
+    guard: G,
+}
+
+impl<G> OwnerDied<G> {
+    pub(crate) fn new(guard: G) -> OwnerDied<G> {
+        OwnerDied { guard }
+    }
+
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> Deref for OwnerDied<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.guard
+    }
+}
+
+impl<G> DerefMut for OwnerDied<G> {
+    fn deref_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> fmt::Debug for OwnerDied<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnerDied").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for OwnerDied<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("previous owner died while holding the lock")
+    }
+}
+
+impl<G> std::error::Error for OwnerDied<G> {}
+
+/// Why a non-blocking (or timed) lock attempt didn't get a normal guard.
+pub enum TryLockError<G> {
+    /// The lock was recovered from a dead owner, see [`OwnerDied`].
+    OwnerDied(OwnerDied<G>),
+    /// Somebody else (who is still alive) is holding it.
+    WouldBlock,
+}
+
+impl<G> From<OwnerDied<G>> for TryLockError<G> {
+    fn from(e: OwnerDied<G>) -> TryLockError<G> {
+        TryLockError::OwnerDied(e)
+    }
+}
+
+impl<G> fmt::Debug for TryLockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::OwnerDied(e) => e.fmt(f),
+            TryLockError::WouldBlock => f.write_str("WouldBlock"),
+        }
+    }
+}
+
+impl<G> fmt::Display for TryLockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::OwnerDied(e) => e.fmt(f),
+            TryLockError::WouldBlock => f.write_str("lock is held by someone else"),
+        }
+    }
+}
+
+impl<G> std::error::Error for TryLockError<G> {}
+
+/// A wait gave up before whatever it was waiting for happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("timed out waiting")]
+pub struct TimedOut;
+
+pub type LockResult<G> = Result<G, OwnerDied<G>>;
+
+pub type TryLockResult<G> = Result<G, TryLockError<G>>;
@@ -0,0 +1,294 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, MmapSafe};
+
+use super::{
+    current_tid, is_alive, LockResult, OwnerDied, TryLockError, TryLockResult, OWNER_CHECK_INTERVAL,
+};
+
+/// set whenever someone might be sleeping on the lock word,
+/// the rest of it is the holder's thread id (or 0 if unlocked)
+const WAITERS: u32 = 1 << 31;
+const OWNER: u32 = !WAITERS;
+
+/// A mutex that lives inside the mapping itself, so it can be shared by
+/// every process that maps the same file.
+///
+/// An all zero `ShmMutex` is unlocked, so it can be used as a field of a
+/// freshly created cell without any initialization.
+///
+/// If the holder dies without unlocking, the next [`lock`](ShmMutex::lock)
+/// returns [`OwnerDied`] with the guard inside so the data can be checked
+/// (and repaired) before carrying on.
+///
+/// The lock word is padded to 8 bytes and nothing may follow `T`, so `T`
+/// has to be aligned to at most 8 bytes and have a size that's a multiple
+/// of 4. Smaller values can be widened or grouped, a lone `u8` won't do:
+/// ```compile_fail
+/// let mutex = mmapcell::sync::ShmMutex::new(0u8);
+/// ```
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{sync::ShmMutex, MmapCell, MmapSafe};
+///
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Shared {
+///     counter: ShmMutex<u64>,
+/// }
+///
+/// let cell = MmapCell::<Shared>::new_named("/tmp/mmapcell-shm-mutex-doc-test.bin").unwrap();
+///
+/// let mut counter = cell.counter.lock().unwrap_or_else(|died| died.into_inner());
+/// *counter += 1;
+/// # }
+/// ```
+#[repr(C)]
+pub struct ShmMutex<T> {
+    state: AtomicU32,
+    _reserved: u32,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: every bit pattern of the lock word is handled (an unknown owner just looks
+// dead) and the data is MmapSafe itself. The reserved word keeps `data` at offset 8,
+// assert_no_padding rejects any T that would leave padding before or after it.
+unsafe impl<T: MmapSafe> MmapSafe for ShmMutex<T> {}
+
+unsafe impl<T: Send> Sync for ShmMutex<T> {}
+
+impl<T> ShmMutex<T> {
+    pub const fn new(value: T) -> ShmMutex<T> {
+        const { assert_no_padding::<T>() };
+
+        ShmMutex {
+            state: AtomicU32::new(0),
+            _reserved: 0,
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Blocks until the lock is acquired.
+    pub fn lock(&self) -> LockResult<ShmMutexGuard<'_, T>> {
+        match self.acquire(None) {
+            Some(died) => self.guard(died),
+            None => unreachable!("lock without a deadline can't time out"),
+        }
+    }
+
+    /// Takes the lock only if nobody (alive) is holding it right now.
+    pub fn try_lock(&self) -> TryLockResult<ShmMutexGuard<'_, T>> {
+        self.lock_timeout(Duration::ZERO)
+    }
+
+    /// Like [`lock`](ShmMutex::lock) but gives up with
+    /// [`TryLockError::WouldBlock`] after `timeout`.
+    pub fn lock_timeout(&self, timeout: Duration) -> TryLockResult<ShmMutexGuard<'_, T>> {
+        match self.acquire(Some(Instant::now() + timeout)) {
+            Some(died) => Ok(self.guard(died)?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Whether anybody is holding the lock right now, alive or not.
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & OWNER != 0
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn guard(&self, died: bool) -> LockResult<ShmMutexGuard<'_, T>> {
+        let guard = ShmMutexGuard {
+            mutex: self,
+            _not_send: PhantomData,
+        };
+
+        match died {
+            true => Err(OwnerDied::new(guard)),
+            false => Ok(guard),
+        }
+    }
+
+    /// Returns whether the lock was taken over from a dead owner,
+    /// or `None` if the deadline passed first.
+    fn acquire(&self, deadline: Option<Instant>) -> Option<bool> {
+        // zeroed mutexes never go through new
+        const { assert_no_padding::<T>() };
+
+        let tid = current_tid();
+        let mut state = self.state.load(Ordering::Relaxed);
+
+        loop {
+            let owner = state & OWNER;
+
+            let (new, died) = match owner {
+                0 => (tid | (state & WAITERS), false),
+                // whoever is left waiting was waiting on the dead owner,
+                // keep the flag so unlock still wakes them
+                owner if !is_alive(owner) => (tid | (state & WAITERS), true),
+                _ => {
+                    let sleep = match deadline {
+                        None => OWNER_CHECK_INTERVAL,
+                        Some(deadline) => {
+                            let left = deadline.saturating_duration_since(Instant::now());
+
+                            if left.is_zero() {
+                                return None;
+                            }
+
+                            left.min(OWNER_CHECK_INTERVAL)
+                        }
+                    };
+
+                    if state & WAITERS == 0 {
+                        if let Err(actual) = self.state.compare_exchange(
+                            state,
+                            state | WAITERS,
+                            Ordering::Relaxed,
+                            Ordering::Relaxed,
+                        ) {
+                            state = actual;
+                            continue;
+                        }
+                    }
+
+                    futex::wait(&self.state, state | WAITERS, Some(sleep));
+
+                    state = self.state.load(Ordering::Relaxed);
+                    continue;
+                }
+            };
+
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(died),
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(0, Ordering::Release) & WAITERS != 0 {
+            futex::wake_one(&self.state);
+        }
+    }
+}
+
+const fn assert_no_padding<T>() {
+    assert!(
+        size_of::<ShmMutex<T>>() == 8 + size_of::<T>(),
+        "ShmMutex needs a type aligned to at most 8 bytes with a size that's a multiple of 4"
+    );
+}
+
+impl<T: Default> Default for ShmMutex<T> {
+    fn default() -> ShmMutex<T> {
+        ShmMutex::new(T::default())
+    }
+}
+
+/// Unlocks the [`ShmMutex`] on drop.
+///
+/// The lock is recorded against the thread that took it, so the guard
+/// can't be sent to another thread.
+pub struct ShmMutexGuard<'a, T> {
+    pub(super) mutex: &'a ShmMutex<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for ShmMutexGuard<'_, T> {}
+
+impl<T> Deref for ShmMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for ShmMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for ShmMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+
+    #[repr(C)]
+    struct Counter {
+        count: ShmMutex<u64>,
+    }
+
+    // SAFETY: a single MmapSafe field
+    unsafe impl MmapSafe for Counter {}
+
+    #[test]
+    fn mutex_across_mappings() {
+        let path = std::env::temp_dir().join("mmapcell-shm-mutex-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        // separate mappings of the same file stand in for separate processes
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = MmapCell::<Counter>::new_named(&path).unwrap();
+
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *cell.count.lock().unwrap() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        let cell = MmapCell::<Counter>::open_named(&path).unwrap();
+        assert_eq!(*cell.count.lock().unwrap(), 4000);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_owner() {
+        let cell = MmapCell::<Counter>::new_anon().unwrap();
+
+        let dead = std::thread::spawn(current_tid).join().unwrap();
+        cell.count.state.store(dead, Ordering::Relaxed);
+
+        assert!(matches!(
+            cell.count.lock_timeout(Duration::from_millis(10)),
+            Err(TryLockError::OwnerDied(_))
+        ));
+
+        // recovering hands the lock back as normal
+        let held = cell.count.lock().unwrap();
+        assert!(matches!(
+            cell.count.try_lock(),
+            Err(TryLockError::WouldBlock)
+        ));
+
+        drop(held);
+        assert!(!cell.count.is_locked());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    cell::UnsafeCell,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{futex, MmapCell, MmapCellError, MmapSafe};
+
+use super::{current_tid, is_alive, OWNER_CHECK_INTERVAL};
+
+/// nobody has started initializing yet, anything between
+/// this and DONE is the thread id of the one that is
+const UNINIT: u32 = 0;
+const DONE: u32 = u32::MAX;
+
+/// What actually lives in the file.
+#[repr(C)]
+struct OnceState<T> {
+    state: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+/// A `OnceCell` shared between processes through a named mapping.
+///
+/// The initializer runs exactly once across every process that opens the same file.
+/// Everyone else blocks on a futex until it's done, and if the initializing process
+/// dies halfway through the next one to notice takes over and runs its own initializer.
+///
+/// # Example
+/// ```rust
+/// use mmapcell::sync::SharedOnce;
+///
+/// let once = SharedOnce::<[u64; 8]>::open("/tmp/mmapcell-shared-once-doc-test.bin").unwrap();
+///
+/// let table = once.get_or_init(|| [7; 8]);
+///
+/// assert_eq!(table[0], 7);
+/// ```
+pub struct SharedOnce<T> {
+    cell: MmapCell<OnceState<T>>,
+}
+
+// SAFETY: the value is only ever written by the one thread that wins the state word
+// and only ever read once it's DONE, exactly like std's OnceLock
+unsafe impl<T: Send + Sync> Sync for SharedOnce<T> {}
+unsafe impl<T: Send> Send for SharedOnce<T> {}
+
+impl<T: MmapSafe> SharedOnce<T> {
+    /// Opens (or creates) the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SharedOnce<T>, MmapCellError> {
+        let options = MmapCell::<OnceState<T>>::options().create(true);
+
+        // SAFETY: zeroes are UNINIT and T is MmapSafe so it is valid for whatever
+        // bytes end up in the file, even half written ones from a dead initializer
+        let cell = unsafe { options.open_unchecked(path)? };
+
+        Ok(SharedOnce { cell })
+    }
+}
+
+impl<T> SharedOnce<T> {
+    fn state(&self) -> &AtomicU32 {
+        &self.cell.get().state
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state().load(Ordering::Acquire) == DONE
+    }
+
+    /// The value, if some process has finished initializing it.
+    pub fn get(&self) -> Option<&T> {
+        match self.is_initialized() {
+            true => Some(unsafe { &*self.cell.get().value.get() }),
+            false => None,
+        }
+    }
+
+    /// The value, running `init` first if no process has initialized it yet.
+    ///
+    /// Blocks while another process is initializing. If that process dies (or `init`
+    /// panics) before it's done, the next caller runs its own `init` instead.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, init: F) -> &T {
+        let tid = current_tid();
+        let state = self.state();
+        let mut current = state.load(Ordering::Acquire);
+
+        loop {
+            let claim_from = match current {
+                DONE => return unsafe { &*self.cell.get().value.get() },
+                UNINIT => UNINIT,
+                owner if !is_alive(owner) => owner,
+                owner => {
+                    futex::wait(state, owner, Some(OWNER_CHECK_INTERVAL));
+                    current = state.load(Ordering::Acquire);
+                    continue;
+                }
+            };
+
+            match state.compare_exchange(claim_from, tid, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+
+        // hands initialization to the next caller if init unwinds
+        struct Reset<'a>(&'a AtomicU32);
+
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                self.0.store(UNINIT, Ordering::Release);
+                futex::wake_all(self.0);
+            }
+        }
+
+        let reset = Reset(state);
+        let value = init();
+        std::mem::forget(reset);
+
+        unsafe { self.cell.get().value.get().write(value) };
+
+        state.store(DONE, Ordering::Release);
+        futex::wake_all(state);
+
+        unsafe { &*self.cell.get().value.get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn initializes_once() {
+        let path = std::env::temp_dir().join("mmapcell-shared-once-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let runs = Arc::new(AtomicU32::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|i| {
+                let (path, runs, barrier) = (path.clone(), runs.clone(), barrier.clone());
+
+                std::thread::spawn(move || {
+                    let once = SharedOnce::<u64>::open(&path).unwrap();
+                    barrier.wait();
+
+                    *once.get_or_init(|| {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                        i + 1
+                    })
+                })
+            })
+            .collect();
+
+        let values: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(values.iter().all(|&v| v == values[0]));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_initializer_is_retried() {
+        let path = std::env::temp_dir().join("mmapcell-shared-once-dead-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let once = SharedOnce::<u64>::open(&path).unwrap();
+
+        // pretend a thread that has since exited was halfway through initializing
+        let dead = std::thread::spawn(current_tid).join().unwrap();
+        once.state().store(dead, Ordering::Release);
+
+        assert_eq!(once.get(), None);
+        assert_eq!(*once.get_or_init(|| 5), 5);
+        assert_eq!(once.get(), Some(&5));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
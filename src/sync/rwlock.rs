@@ -0,0 +1,606 @@
+use std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, MmapSafe};
+
+use super::{
+    current_tid, is_alive, LockResult, OwnerDied, TryLockError, TryLockResult, OWNER_CHECK_INTERVAL,
+};
+
+/// How many readers can hold a [`ShmRwLock`] at once, any more wait for a free slot.
+pub const READER_SLOTS: usize = 64;
+
+/// Who gets to go first when readers and a writer are both waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RwLockPolicy {
+    /// New readers hold off while a writer is waiting, so updates can't starve.
+    #[default]
+    PreferWriter,
+    /// Writers only get in once there are no readers at all.
+    PreferReader,
+}
+
+impl RwLockPolicy {
+    fn from_raw(raw: u32) -> RwLockPolicy {
+        match raw {
+            1 => RwLockPolicy::PreferReader,
+            _ => RwLockPolicy::PreferWriter,
+        }
+    }
+
+    const fn to_raw(self) -> u32 {
+        match self {
+            RwLockPolicy::PreferWriter => 0,
+            RwLockPolicy::PreferReader => 1,
+        }
+    }
+}
+
+/// A reader-writer lock that lives inside the mapping itself, so it can
+/// be shared by every process that maps the same file.
+///
+/// Each reader takes one of [`READER_SLOTS`] slots and records its thread id
+/// there, next to the writer's. Slots left behind by dead readers are
+/// reclaimed quietly, while a dead writer makes the next lock of either kind
+/// return [`OwnerDied`] since it may have left the data half updated.
+///
+/// An all zero `ShmRwLock` is unlocked and uses [`RwLockPolicy::PreferWriter`].
+///
+/// Like [`ShmMutex`](super::ShmMutex), `T` has to be aligned to at most 8 bytes
+/// and have a size that's a multiple of 4.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{sync::ShmRwLock, MmapCell, MmapSafe};
+///
+/// #[derive(MmapSafe)]
+/// #[repr(C)]
+/// struct Config {
+///     limits: ShmRwLock<[u32; 16]>,
+/// }
+///
+/// let cell = MmapCell::<Config>::new_named("/tmp/mmapcell-shm-rwlock-doc-test.bin").unwrap();
+///
+/// cell.limits.write().unwrap_or_else(|died| died.into_inner())[0] = 100;
+///
+/// assert_eq!(cell.limits.read().unwrap_or_else(|died| died.into_inner())[0], 100);
+/// # }
+/// ```
+#[repr(C)]
+pub struct ShmRwLock<T> {
+    writer: AtomicU32,
+    /// a writer waiting under PreferWriter, by thread id so a dead
+    /// one can't hold readers off forever
+    waiting_writer: AtomicU32,
+    sleepers: AtomicU32,
+    /// bumped on every release, it's what everybody sleeps on
+    epoch: AtomicU32,
+    policy: AtomicU32,
+    /// set when a dead writer was cleared without anybody getting OwnerDied,
+    /// so the next holder still hears about it
+    poisoned: AtomicU32,
+    readers: [AtomicU32; READER_SLOTS],
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: every bit pattern of the lock words is handled (unknown owners just look
+// dead, unknown policies are PreferWriter) and the data is MmapSafe itself.
+// The lock words add up to a multiple of 8 and assert_no_padding rejects
+// any T that would leave padding before or after the data.
+unsafe impl<T: MmapSafe> MmapSafe for ShmRwLock<T> {}
+
+unsafe impl<T: Send + Sync> Sync for ShmRwLock<T> {}
+
+const fn assert_no_padding<T>() {
+    assert!(
+        size_of::<ShmRwLock<T>>() == (6 + READER_SLOTS) * 4 + size_of::<T>(),
+        "ShmRwLock needs a type aligned to at most 8 bytes with a size that's a multiple of 4"
+    );
+}
+
+impl<T> ShmRwLock<T> {
+    pub const fn new(value: T) -> ShmRwLock<T> {
+        ShmRwLock::with_policy(value, RwLockPolicy::PreferWriter)
+    }
+
+    pub const fn with_policy(value: T, policy: RwLockPolicy) -> ShmRwLock<T> {
+        const { assert_no_padding::<T>() };
+
+        ShmRwLock {
+            writer: AtomicU32::new(0),
+            waiting_writer: AtomicU32::new(0),
+            sleepers: AtomicU32::new(0),
+            epoch: AtomicU32::new(0),
+            policy: AtomicU32::new(policy.to_raw()),
+            poisoned: AtomicU32::new(0),
+            readers: [const { AtomicU32::new(0) }; READER_SLOTS],
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn policy(&self) -> RwLockPolicy {
+        RwLockPolicy::from_raw(self.policy.load(Ordering::Relaxed))
+    }
+
+    /// Changes the policy for every process sharing the lock,
+    /// handy for locks that started out zeroed.
+    pub fn set_policy(&self, policy: RwLockPolicy) {
+        self.policy.store(policy.to_raw(), Ordering::Relaxed);
+    }
+
+    /// Blocks until shared access is acquired.
+    pub fn read(&self) -> LockResult<ShmRwLockReadGuard<'_, T>> {
+        match self.acquire_read(None) {
+            Some((slot, died)) => self.read_guard(slot, died),
+            None => unreachable!("lock without a deadline can't time out"),
+        }
+    }
+
+    pub fn try_read(&self) -> TryLockResult<ShmRwLockReadGuard<'_, T>> {
+        self.read_timeout(Duration::ZERO)
+    }
+
+    /// Like [`read`](ShmRwLock::read) but gives up with
+    /// [`TryLockError::WouldBlock`] after `timeout`.
+    pub fn read_timeout(&self, timeout: Duration) -> TryLockResult<ShmRwLockReadGuard<'_, T>> {
+        match self.acquire_read(Some(Instant::now() + timeout)) {
+            Some((slot, died)) => Ok(self.read_guard(slot, died)?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Blocks until exclusive access is acquired.
+    pub fn write(&self) -> LockResult<ShmRwLockWriteGuard<'_, T>> {
+        match self.acquire_write(None) {
+            Some(died) => self.write_guard(died),
+            None => unreachable!("lock without a deadline can't time out"),
+        }
+    }
+
+    pub fn try_write(&self) -> TryLockResult<ShmRwLockWriteGuard<'_, T>> {
+        self.write_timeout(Duration::ZERO)
+    }
+
+    /// Like [`write`](ShmRwLock::write) but gives up with
+    /// [`TryLockError::WouldBlock`] after `timeout`.
+    pub fn write_timeout(&self, timeout: Duration) -> TryLockResult<ShmRwLockWriteGuard<'_, T>> {
+        match self.acquire_write(Some(Instant::now() + timeout)) {
+            Some(died) => Ok(self.write_guard(died)?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn read_guard(&self, slot: usize, died: bool) -> LockResult<ShmRwLockReadGuard<'_, T>> {
+        let guard = ShmRwLockReadGuard {
+            lock: self,
+            slot,
+            _not_send: PhantomData,
+        };
+
+        match died {
+            true => Err(OwnerDied::new(guard)),
+            false => Ok(guard),
+        }
+    }
+
+    fn write_guard(&self, died: bool) -> LockResult<ShmRwLockWriteGuard<'_, T>> {
+        let guard = ShmRwLockWriteGuard {
+            lock: self,
+            _not_send: PhantomData,
+        };
+
+        match died {
+            true => Err(OwnerDied::new(guard)),
+            false => Ok(guard),
+        }
+    }
+
+    /// Returns the reader slot taken and whether a dead writer was cleared
+    /// on the way, or `None` if the deadline passed first.
+    fn acquire_read(&self, deadline: Option<Instant>) -> Option<(usize, bool)> {
+        const { assert_no_padding::<T>() };
+
+        let tid = current_tid();
+        let mut died = false;
+
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let writer = self.writer.load(Ordering::SeqCst);
+
+            if writer != 0 {
+                if !is_alive(writer) {
+                    if self
+                        .writer
+                        .compare_exchange(writer, 0, Ordering::SeqCst, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        died = true;
+                        self.release();
+                    }
+
+                    continue;
+                }
+
+                self.sleep_reporting(epoch, deadline, died)?;
+                continue;
+            }
+
+            if self.policy() == RwLockPolicy::PreferWriter && self.writer_waiting() {
+                self.sleep_reporting(epoch, deadline, died)?;
+                continue;
+            }
+
+            let Some(slot) = self.claim_slot(tid) else {
+                self.sleep_reporting(epoch, deadline, died)?;
+                continue;
+            };
+
+            // a writer might have slipped in between checking and taking the slot,
+            // it scans the slots after taking the writer word so one of us notices
+            if self.writer.load(Ordering::SeqCst) != 0 {
+                self.readers[slot].store(0, Ordering::SeqCst);
+                self.release();
+                continue;
+            }
+
+            return Some((slot, died | self.take_poison()));
+        }
+    }
+
+    /// Returns whether the writer word was taken over from a dead
+    /// writer, or `None` if the deadline passed first.
+    fn acquire_write(&self, deadline: Option<Instant>) -> Option<bool> {
+        const { assert_no_padding::<T>() };
+
+        let tid = current_tid();
+        let prefer_writer = self.policy() == RwLockPolicy::PreferWriter;
+
+        let died = loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let writer = self.writer.load(Ordering::SeqCst);
+
+            let died = match writer {
+                0 if !prefer_writer && !self.readers_clear() => None,
+                0 => Some(false),
+                writer if !is_alive(writer) => Some(true),
+                _ => None,
+            };
+
+            if let Some(died) = died {
+                match self
+                    .writer
+                    .compare_exchange(writer, tid, Ordering::SeqCst, Ordering::Relaxed)
+                {
+                    Ok(_) => break died,
+                    Err(_) => continue,
+                }
+            }
+
+            if prefer_writer {
+                self.register_waiting(tid);
+            }
+
+            if self.sleep(epoch, deadline).is_none() {
+                self.unregister_waiting(tid);
+                return None;
+            }
+        };
+
+        self.unregister_waiting(tid);
+
+        // new readers back off now, wait for the ones already in to leave
+        loop {
+            let epoch = self.epoch.load(Ordering::SeqCst);
+
+            if self.readers_clear() {
+                return Some(died | self.take_poison());
+            }
+
+            if self.sleep(epoch, deadline).is_none() {
+                // giving up mustn't swallow the dead writer we took over from
+                if died {
+                    self.poisoned.store(1, Ordering::SeqCst);
+                }
+
+                self.writer.store(0, Ordering::SeqCst);
+                self.release();
+                return None;
+            }
+        }
+    }
+
+    /// Whether a dead writer was left unreported, clearing it if so.
+    fn take_poison(&self) -> bool {
+        self.poisoned.load(Ordering::SeqCst) != 0 && self.poisoned.swap(0, Ordering::SeqCst) != 0
+    }
+
+    fn claim_slot(&self, tid: u32) -> Option<usize> {
+        let free = self.readers.iter().position(|slot| {
+            slot.compare_exchange(0, tid, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+        });
+
+        free.or_else(|| {
+            self.readers.iter().position(|slot| {
+                let reader = slot.load(Ordering::Relaxed);
+
+                !is_alive(reader)
+                    && slot
+                        .compare_exchange(reader, tid, Ordering::SeqCst, Ordering::Relaxed)
+                        .is_ok()
+            })
+        })
+    }
+
+    /// Whether every reader slot is free, clearing the ones held by dead readers.
+    fn readers_clear(&self) -> bool {
+        self.readers.iter().all(|slot| {
+            let reader = slot.load(Ordering::SeqCst);
+
+            reader == 0
+                || (!is_alive(reader)
+                    && slot
+                        .compare_exchange(reader, 0, Ordering::SeqCst, Ordering::Relaxed)
+                        .is_ok())
+        })
+    }
+
+    fn writer_waiting(&self) -> bool {
+        let waiting = self.waiting_writer.load(Ordering::Relaxed);
+
+        waiting != 0 && is_alive(waiting)
+    }
+
+    fn register_waiting(&self, tid: u32) {
+        let waiting = self.waiting_writer.load(Ordering::Relaxed);
+
+        if waiting == 0 || !is_alive(waiting) {
+            let _ = self.waiting_writer.compare_exchange(
+                waiting,
+                tid,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    fn unregister_waiting(&self, tid: u32) {
+        let _ = self
+            .waiting_writer
+            .compare_exchange(tid, 0, Ordering::Relaxed, Ordering::Relaxed);
+
+        // readers might be holding off for us
+        self.release();
+    }
+
+    /// Sleeps until the next release, or returns `None` if the deadline has passed.
+    fn sleep(&self, epoch: u32, deadline: Option<Instant>) -> Option<()> {
+        let timeout = match deadline {
+            None => OWNER_CHECK_INTERVAL,
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+
+                if left.is_zero() {
+                    return None;
+                }
+
+                left.min(OWNER_CHECK_INTERVAL)
+            }
+        };
+
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        futex::wait(&self.epoch, epoch, Some(timeout));
+        self.sleepers.fetch_sub(1, Ordering::Relaxed);
+
+        Some(())
+    }
+
+    /// Like [`sleep`](ShmRwLock::sleep) but if the deadline has passed, a dead writer
+    /// that was already cleared is left for the next holder to hear about.
+    fn sleep_reporting(&self, epoch: u32, deadline: Option<Instant>, died: bool) -> Option<()> {
+        let slept = self.sleep(epoch, deadline);
+
+        if slept.is_none() && died {
+            self.poisoned.store(1, Ordering::SeqCst);
+        }
+
+        slept
+    }
+
+    fn release(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            futex::wake_all(&self.epoch);
+        }
+    }
+}
+
+impl<T: Default> Default for ShmRwLock<T> {
+    fn default() -> ShmRwLock<T> {
+        ShmRwLock::new(T::default())
+    }
+}
+
+/// Releases shared access to a [`ShmRwLock`] on drop.
+///
+/// The reader slot is recorded against the thread that took it,
+/// so the guard can't be sent to another thread.
+pub struct ShmRwLockReadGuard<'a, T> {
+    lock: &'a ShmRwLock<T>,
+    slot: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for ShmRwLockReadGuard<'_, T> {}
+
+impl<T> Deref for ShmRwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for ShmRwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.readers[self.slot].store(0, Ordering::SeqCst);
+        self.lock.release();
+    }
+}
+
+/// Releases exclusive access to a [`ShmRwLock`] on drop.
+///
+/// The lock is recorded against the thread that took it,
+/// so the guard can't be sent to another thread.
+pub struct ShmRwLockWriteGuard<'a, T> {
+    lock: &'a ShmRwLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for ShmRwLockWriteGuard<'_, T> {}
+
+impl<T> Deref for ShmRwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for ShmRwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for ShmRwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.writer.store(0, Ordering::SeqCst);
+        self.lock.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+
+    #[repr(C)]
+    struct Table {
+        pair: ShmRwLock<[u64; 2]>,
+    }
+
+    // SAFETY: a single MmapSafe field
+    unsafe impl MmapSafe for Table {}
+
+    #[test]
+    fn rwlock_across_mappings() {
+        let path = std::env::temp_dir().join("mmapcell-shm-rwlock-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        for policy in [RwLockPolicy::PreferWriter, RwLockPolicy::PreferReader] {
+            MmapCell::<Table>::new_named(&path)
+                .unwrap()
+                .pair
+                .set_policy(policy);
+
+            // separate mappings of the same file stand in for separate processes
+            let threads: Vec<_> = (0..6)
+                .map(|i| {
+                    let cell = MmapCell::<Table>::new_named(&path).unwrap();
+
+                    std::thread::spawn(move || {
+                        for _ in 0..300 {
+                            if i % 3 == 0 {
+                                let mut pair = cell.pair.write().unwrap();
+                                pair[0] += 1;
+                                pair[1] += 1;
+                            } else {
+                                let pair = cell.pair.read().unwrap();
+                                assert_eq!(pair[0], pair[1]);
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            threads.into_iter().for_each(|t| t.join().unwrap());
+        }
+
+        let cell = MmapCell::<Table>::open_named(&path).unwrap();
+        assert_eq!(*cell.pair.read().unwrap(), [1200, 1200]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_owners() {
+        let cell = MmapCell::<Table>::new_anon().unwrap();
+        let dead = std::thread::spawn(current_tid).join().unwrap();
+
+        // a dead reader's slot doesn't keep writers out
+        cell.pair.readers[3].store(dead, Ordering::Relaxed);
+        assert!(cell.pair.try_write().is_ok());
+
+        // a dead writer is reported to whoever comes next
+        cell.pair.writer.store(dead, Ordering::Relaxed);
+        assert!(matches!(
+            cell.pair.try_read(),
+            Err(TryLockError::OwnerDied(_))
+        ));
+
+        let read = cell.pair.read().unwrap();
+        assert!(matches!(
+            cell.pair.write_timeout(Duration::from_millis(10)),
+            Err(TryLockError::WouldBlock)
+        ));
+
+        drop(read);
+        assert!(cell.pair.try_write().is_ok());
+
+        // taking over from a dead writer and then timing out on a
+        // reader still leaves the death for the next holder to see
+        let read = cell.pair.read().unwrap();
+        cell.pair.writer.store(dead, Ordering::Relaxed);
+        assert!(matches!(
+            cell.pair.write_timeout(Duration::from_millis(10)),
+            Err(TryLockError::WouldBlock)
+        ));
+
+        drop(read);
+        assert!(matches!(
+            cell.pair.try_write(),
+            Err(TryLockError::OwnerDied(_))
+        ));
+        assert!(cell.pair.try_write().is_ok());
+
+        // and so does clearing a dead writer and then timing out as a reader,
+        // here held off by a (live) writer waiting its turn
+        cell.pair.writer.store(dead, Ordering::Relaxed);
+        cell.pair
+            .waiting_writer
+            .store(current_tid(), Ordering::Relaxed);
+        assert!(matches!(
+            cell.pair.read_timeout(Duration::from_millis(10)),
+            Err(TryLockError::WouldBlock)
+        ));
+
+        cell.pair.waiting_writer.store(0, Ordering::Relaxed);
+        assert!(matches!(
+            cell.pair.try_read(),
+            Err(TryLockError::OwnerDied(_))
+        ));
+        assert!(cell.pair.try_read().is_ok());
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{futex, MmapSafe};
+
+use super::{is_alive, TimedOut, OWNER_CHECK_INTERVAL};
+
+/// How many processes can hold permits of a [`ShmSemaphore`] at once.
+pub const HOLDER_SLOTS: usize = 64;
+
+/// A counting semaphore that lives inside the mapping itself, so it can limit
+/// access to something across every process that maps the same file.
+///
+/// Permits are counted per holding process in one of [`HOLDER_SLOTS`] slots,
+/// and the ones held by a process that has died are handed back the next time
+/// somebody finds the semaphore empty.
+///
+/// A zeroed semaphore has no permits at all, so give it its real count through
+/// an initializer like [`MmapCell::open_or_create`](crate::MmapCell::open_or_create).
+///
+/// # Example
+/// ```rust
+/// use mmapcell::{sync::ShmSemaphore, MmapCell};
+///
+/// let path = "/tmp/mmapcell-semaphore-doc-test.bin";
+/// let sem = MmapCell::<ShmSemaphore>::open_or_create(path, || ShmSemaphore::new(2)).unwrap();
+///
+/// let first = sem.acquire();
+/// let second = sem.try_acquire().unwrap();
+///
+/// assert!(sem.try_acquire().is_none());
+///
+/// drop(first);
+/// assert!(sem.try_acquire().is_some());
+/// ```
+#[repr(C)]
+pub struct ShmSemaphore {
+    permits: AtomicU32,
+    available: AtomicU32,
+    sleepers: AtomicU32,
+    _reserved: u32,
+    /// pid in the high half, permits it holds in the low half
+    holders: [AtomicU64; HOLDER_SLOTS],
+}
+
+// SAFETY: any count is handled (an unknown holder just looks dead) and
+// the header words add up to 16 bytes so there's no padding
+unsafe impl MmapSafe for ShmSemaphore {}
+
+impl ShmSemaphore {
+    pub const fn new(permits: u32) -> ShmSemaphore {
+        ShmSemaphore {
+            permits: AtomicU32::new(permits),
+            available: AtomicU32::new(permits),
+            sleepers: AtomicU32::new(0),
+            _reserved: 0,
+            holders: [const { AtomicU64::new(0) }; HOLDER_SLOTS],
+        }
+    }
+
+    /// How many permits the semaphore was created with.
+    pub fn permits(&self) -> u32 {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// How many permits are free right now.
+    pub fn available(&self) -> u32 {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until a permit is free.
+    pub fn acquire(&self) -> ShmSemaphorePermit<'_> {
+        match self.take(None) {
+            Ok(permit) => permit,
+            Err(TimedOut) => unreachable!("acquire without a deadline can't time out"),
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<ShmSemaphorePermit<'_>> {
+        self.take(Some(Instant::now())).ok()
+    }
+
+    /// Like [`acquire`](ShmSemaphore::acquire) but gives up after `timeout`.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Result<ShmSemaphorePermit<'_>, TimedOut> {
+        self.take(Some(Instant::now() + timeout))
+    }
+
+    /// Hands back a permit kept past its guard with [`ShmSemaphorePermit::forget`].
+    ///
+    /// Permits are counted per process, so this has to be called from the
+    /// process that acquired it. Returns `false` and leaves the semaphore
+    /// alone if this process holds no permits.
+    pub fn release(&self) -> bool {
+        let pid = std::process::id() as u64;
+
+        // nobody else touches a live process's slot, but a reclaim
+        // mistaking us for a dead process could still race us
+        let released = self.holders.iter().any(|slot| {
+            let holder = slot.load(Ordering::Relaxed);
+
+            holder >> 32 == pid
+                && holder as u32 != 0
+                && slot
+                    .compare_exchange(
+                        holder,
+                        if holder as u32 == 1 { 0 } else { holder - 1 },
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+        });
+
+        if !released {
+            return false;
+        }
+
+        self.available.fetch_add(1, Ordering::SeqCst);
+
+        if self.sleepers.load(Ordering::SeqCst) != 0 {
+            futex::wake_one(&self.available);
+        }
+
+        true
+    }
+
+    fn take(&self, deadline: Option<Instant>) -> Result<ShmSemaphorePermit<'_>, TimedOut> {
+        let mut available = self.available.load(Ordering::Relaxed);
+
+        loop {
+            if available == 0 {
+                if self.reclaim() {
+                    available = self.available.load(Ordering::Relaxed);
+                    continue;
+                }
+
+                let timeout = match deadline {
+                    None => OWNER_CHECK_INTERVAL,
+                    Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+                        left if left.is_zero() => return Err(TimedOut),
+                        left => left.min(OWNER_CHECK_INTERVAL),
+                    },
+                };
+
+                self.sleepers.fetch_add(1, Ordering::SeqCst);
+                futex::wait(&self.available, 0, Some(timeout));
+                self.sleepers.fetch_sub(1, Ordering::Relaxed);
+
+                available = self.available.load(Ordering::Relaxed);
+                continue;
+            }
+
+            if let Err(actual) = self.available.compare_exchange(
+                available,
+                available - 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                available = actual;
+                continue;
+            }
+
+            // dying right here leaks the permit, there's no way to record
+            // it against us in the same step as taking it
+            if self.record() {
+                return Ok(ShmSemaphorePermit { semaphore: self });
+            }
+
+            // no room to record it, hand it back and wait for a slot to free up
+            self.available.fetch_add(1, Ordering::SeqCst);
+
+            if !self.reclaim() {
+                let pause = match deadline {
+                    None => OWNER_CHECK_INTERVAL,
+                    Some(deadline) => match deadline.saturating_duration_since(Instant::now()) {
+                        left if left.is_zero() => return Err(TimedOut),
+                        left => left.min(OWNER_CHECK_INTERVAL),
+                    },
+                };
+
+                std::thread::sleep(pause);
+            }
+
+            available = self.available.load(Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a permit against this process, if there's a slot for it.
+    fn record(&self) -> bool {
+        let pid = (std::process::id() as u64) << 32;
+
+        let ours = self.holders.iter().find(|slot| {
+            let holder = slot.load(Ordering::Relaxed);
+
+            holder >> 32 == pid >> 32
+                && slot
+                    .compare_exchange(holder, holder + 1, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+        });
+
+        ours.is_some()
+            || self.holders.iter().any(|slot| {
+                slot.compare_exchange(0, pid | 1, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            })
+    }
+
+    /// Hands back every permit held by a dead process, returns whether there were any.
+    fn reclaim(&self) -> bool {
+        let mut reclaimed = 0;
+
+        for slot in &self.holders {
+            let holder = slot.load(Ordering::Relaxed);
+
+            if holder != 0
+                && !is_alive((holder >> 32) as u32)
+                && slot
+                    .compare_exchange(holder, 0, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                reclaimed += holder as u32;
+            }
+        }
+
+        if reclaimed != 0 {
+            self.available.fetch_add(reclaimed, Ordering::SeqCst);
+            futex::wake_all(&self.available);
+        }
+
+        reclaimed != 0
+    }
+}
+
+/// A permit from a [`ShmSemaphore`], handed back on drop.
+pub struct ShmSemaphorePermit<'a> {
+    semaphore: &'a ShmSemaphore,
+}
+
+impl ShmSemaphorePermit<'_> {
+    /// Keeps the permit without a guard, hand it back later
+    /// with [`ShmSemaphore::release`].
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for ShmSemaphorePermit<'_> {
+    fn drop(&mut self) {
+        let _ = self.semaphore.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+
+    #[test]
+    fn dead_holder_reclaimed() {
+        let sem = MmapCell::<ShmSemaphore>::new_anon().unwrap();
+
+        // a zeroed semaphore has no permits
+        assert!(sem.try_acquire().is_none());
+
+        // a real process that has exited and been reaped
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+
+        // a dead process holding both permits of a semaphore created with two
+        sem.permits.store(2, Ordering::Relaxed);
+        sem.holders[5].store((dead as u64) << 32 | 2, Ordering::Relaxed);
+
+        let first = sem.acquire_timeout(Duration::from_millis(10)).unwrap();
+        let second = sem.try_acquire().unwrap();
+        assert_eq!(
+            sem.acquire_timeout(Duration::from_millis(10)).err(),
+            Some(TimedOut)
+        );
+
+        second.forget();
+        drop(first);
+        assert_eq!(sem.available(), 1);
+
+        assert!(sem.release());
+        assert_eq!(sem.available(), 2);
+        assert!(sem.holders.iter().all(|h| h.load(Ordering::Relaxed) == 0));
+
+        // releasing without holding anything can't make up a permit
+        assert!(!sem.release());
+        assert_eq!(sem.available(), 2);
+    }
+}
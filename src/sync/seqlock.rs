@@ -0,0 +1,277 @@
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{self, AtomicU32, Ordering},
+};
+
+use crate::MmapSafe;
+
+use super::{current_tid, is_alive, LockResult, OwnerDied};
+
+/// How many times [`ShmSeqLock::load`] retries before checking on the writer.
+const SPINS_PER_WRITER_CHECK: u32 = 64;
+
+/// A seqlock that lives inside the mapping itself, for `Copy` data that is
+/// written rarely and read a lot by other processes.
+///
+/// Readers never block writers, they just retry until they get a copy no
+/// [`store`](ShmSeqLock::store) ran through the middle of. Writers are serialized
+/// among themselves by thread id, so a writer that dies halfway through is taken
+/// over by the next one. A [`load`](ShmSeqLock::load) that keeps finding a store in
+/// progress checks on the writer, and if it died finishes the store for it and
+/// returns [`OwnerDied`] with whatever the dead writer left behind. That's the only
+/// time a reader writes to the mapping.
+///
+/// An all zero `ShmSeqLock` holds an all zero `T`.
+///
+/// Like [`ShmMutex`](super::ShmMutex), `T` has to be aligned to at most 8 bytes
+/// and have a size that's a multiple of 4.
+///
+/// # Example
+/// ```rust
+/// # #[cfg(feature = "derive")] {
+/// use mmapcell::{sync::ShmSeqLock, MmapCell, MmapSafe};
+///
+/// #[derive(MmapSafe, Clone, Copy)]
+/// #[repr(C)]
+/// struct Telemetry {
+///     packets: u64,
+///     bytes: u64,
+/// }
+///
+/// let cell =
+///     MmapCell::<ShmSeqLock<Telemetry>>::new_named("/tmp/mmapcell-seqlock-doc-test.bin").unwrap();
+///
+/// cell.store(Telemetry { packets: 1, bytes: 1500 });
+///
+/// let snapshot = cell.load().unwrap_or_else(|died| died.into_inner());
+/// assert_eq!(snapshot.bytes, 1500);
+/// # }
+/// ```
+#[repr(C)]
+pub struct ShmSeqLock<T> {
+    /// odd while a store is in progress
+    seq: AtomicU32,
+    writer: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+// SAFETY: every bit pattern of the two words is handled (an unknown writer just looks
+// dead) and the data is MmapSafe itself, so even a torn copy is a valid T.
+// assert_no_padding rejects any T that would leave padding before or after it.
+unsafe impl<T: MmapSafe> MmapSafe for ShmSeqLock<T> {}
+
+unsafe impl<T: Send> Sync for ShmSeqLock<T> {}
+
+const fn assert_no_padding<T>() {
+    assert!(
+        size_of::<ShmSeqLock<T>>() == 8 + size_of::<T>(),
+        "ShmSeqLock needs a type aligned to at most 8 bytes with a size that's a multiple of 4"
+    );
+}
+
+impl<T> ShmSeqLock<T> {
+    pub const fn new(value: T) -> ShmSeqLock<T> {
+        const { assert_no_padding::<T>() };
+
+        ShmSeqLock {
+            seq: AtomicU32::new(0),
+            writer: AtomicU32::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// How many stores have completed (wrapping), handy for spotting changes
+    /// without copying the data out.
+    pub fn sequence(&self) -> u32 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+}
+
+impl<T: MmapSafe + Copy> ShmSeqLock<T> {
+    /// A consistent copy of the value, spinning for as long as a store is in progress.
+    ///
+    /// If the writer died in the middle of a store this returns [`OwnerDied`] with
+    /// the value it left behind, which may be half written, and the next load
+    /// carries on as normal.
+    pub fn load(&self) -> LockResult<T> {
+        let mut spins = 0u32;
+
+        loop {
+            if let Some(value) = self.try_load() {
+                return Ok(value);
+            }
+
+            spins = spins.wrapping_add(1);
+
+            if spins.is_multiple_of(SPINS_PER_WRITER_CHECK) {
+                if let Some(value) = self.recover() {
+                    return Err(OwnerDied::new(value));
+                }
+            }
+
+            std::thread::yield_now();
+        }
+    }
+
+    /// Finishes the store of a writer that died halfway through, returning
+    /// what it left behind, or `None` if there was no such store.
+    fn recover(&self) -> Option<T> {
+        let writer = self.writer.load(Ordering::Relaxed);
+
+        if writer != 0 && is_alive(writer) {
+            return None;
+        }
+
+        let tid = current_tid();
+
+        if self
+            .writer
+            .compare_exchange(writer, tid, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        let seq = self.seq.load(Ordering::Relaxed);
+
+        // somebody else got there first
+        if seq.is_multiple_of(2) {
+            self.writer.store(0, Ordering::Release);
+            return None;
+        }
+
+        // SAFETY: T is MmapSafe and nobody else is writing now
+        let value = unsafe { self.data.get().read_volatile() };
+
+        self.seq.store(seq.wrapping_add(1), Ordering::Release);
+        self.writer.store(0, Ordering::Release);
+
+        Some(value)
+    }
+
+    /// A consistent copy of the value, or `None` if a store was in progress.
+    pub fn try_load(&self) -> Option<T> {
+        const { assert_no_padding::<T>() };
+
+        let before = self.seq.load(Ordering::Acquire);
+
+        if before % 2 == 1 {
+            return None;
+        }
+
+        // SAFETY: T is MmapSafe so whatever half written bytes we
+        // race with are still a valid T, we just throw it away
+        let value = unsafe { self.data.get().read_volatile() };
+
+        atomic::fence(Ordering::Acquire);
+
+        match self.seq.load(Ordering::Relaxed) == before {
+            true => Some(value),
+            false => None,
+        }
+    }
+
+    /// Replaces the value, waiting for any other writer to finish first.
+    pub fn store(&self, value: T) {
+        const { assert_no_padding::<T>() };
+
+        let tid = current_tid();
+
+        loop {
+            let writer = self.writer.load(Ordering::Relaxed);
+
+            if (writer == 0 || !is_alive(writer))
+                && self
+                    .writer
+                    .compare_exchange(writer, tid, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break;
+            }
+
+            std::thread::yield_now();
+        }
+
+        // a dead writer might have left it odd already
+        let seq = self.seq.load(Ordering::Relaxed) | 1;
+
+        self.seq.store(seq, Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+
+        unsafe { self.data.get().write_volatile(value) };
+
+        self.seq.store(seq.wrapping_add(1), Ordering::Release);
+        self.writer.store(0, Ordering::Release);
+    }
+}
+
+impl<T: Default> Default for ShmSeqLock<T> {
+    fn default() -> ShmSeqLock<T> {
+        ShmSeqLock::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapCell;
+
+    #[test]
+    fn torn_free_loads() {
+        let path = std::env::temp_dir().join("mmapcell-seqlock-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let writer = MmapCell::<ShmSeqLock<[u64; 8]>>::new_named(&path).unwrap();
+        let reader = MmapCell::<ShmSeqLock<[u64; 8]>>::open_named(&path).unwrap();
+
+        let writer = std::thread::spawn(move || {
+            for i in 0..10_000 {
+                writer.store([i; 8]);
+            }
+        });
+
+        let mut last = 0;
+
+        while !writer.is_finished() {
+            let snapshot = reader.load().unwrap();
+
+            assert!(snapshot.iter().all(|&v| v == snapshot[0]));
+            assert!(snapshot[0] >= last);
+
+            last = snapshot[0];
+        }
+
+        writer.join().unwrap();
+
+        assert_eq!(reader.load().unwrap(), [9_999; 8]);
+        assert_eq!(reader.sequence(), 10_000);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dead_writer() {
+        let cell = MmapCell::<ShmSeqLock<[u32; 2]>>::new_anon().unwrap();
+        cell.store([1, 1]);
+
+        // a writer that died halfway through, with the seq left odd
+        let dead = std::thread::spawn(current_tid).join().unwrap();
+        cell.writer.store(dead, Ordering::Relaxed);
+        cell.seq.store(3, Ordering::Relaxed);
+        unsafe { (*cell.data.get())[0] = 2 };
+
+        assert_eq!(cell.try_load(), None);
+        assert_eq!(cell.load().map_err(|died| died.into_inner()), Err([2, 1]));
+
+        // the store was finished for it, so readers and writers carry on
+        assert_eq!(cell.load().unwrap(), [2, 1]);
+        assert_eq!(cell.sequence(), 2);
+
+        cell.store([3, 3]);
+        assert_eq!(cell.load().unwrap(), [3, 3]);
+    }
+}
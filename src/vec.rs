@@ -0,0 +1,364 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    path::Path,
+};
+
+use crate::{
+    options::Remap, LayoutError, MmapCell, MmapCellError, MmapCellOptions, MmapSafe, SizePolicy,
+};
+
+/// What sits at the start of every [`MmapVec`] file, before the elements.
+#[repr(C)]
+struct VecHeader {
+    len: u64,
+    capacity: u64,
+}
+
+// SAFETY: two plain words, a capacity that doesn't fit the file is rejected on open
+unsafe impl MmapSafe for VecHeader {}
+
+/// A growable vector of `T` backed by a file.
+///
+/// The file holds a small length/capacity header followed by `capacity`
+/// elements. Growing it extends the file and maps it again, so unlike
+/// [`MmapCell`] the file size isn't fixed up front.
+///
+/// Elements are written before the length is bumped, so a process that
+/// dies halfway through a `push` leaves the vector as if it never happened.
+/// Removed elements are never dropped, they simply stop being part of the vector.
+///
+/// An `MmapVec` is meant to have a single writer, nothing stops two processes
+/// growing the same file at once unless it's opened with a
+/// [`lock`](MmapCellOptions::lock) through [`MmapVec::open_with_options`].
+///
+/// # Example
+/// ```rust
+/// use mmapcell::MmapVec;
+///
+/// let path = "/tmp/mmapcell-vec-doc-test.bin";
+/// let _ = std::fs::remove_file(path);
+///
+/// let mut records = MmapVec::<u64>::open(path).unwrap();
+/// records.push(1).unwrap();
+/// records.extend_from_slice(&[2, 3]).unwrap();
+/// drop(records);
+///
+/// let records = MmapVec::<u64>::open(path).unwrap();
+/// assert_eq!(&records[..], &[1, 2, 3]);
+/// ```
+pub struct MmapVec<T> {
+    cell: MmapCell<VecHeader>,
+    remap: Remap,
+    /// padding between the header and the first element
+    pad: usize,
+    _inner: PhantomData<T>,
+}
+
+impl<T> Deref for MmapVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for MmapVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: MmapSafe> MmapVec<T> {
+    /// Opens the vector at `path`, creating an empty one if the file doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MmapVec<T>, MmapCellError> {
+        MmapVec::open_with_options(MmapCell::options().create(true), path)
+    }
+
+    /// Opens the vector at `path` the way `options` says, say with a
+    /// [`lock`](MmapCellOptions::lock) or a [`header`](MmapCellOptions::header).
+    ///
+    /// The vector sizes the file itself, so resizing, the size policy and the
+    /// initializer don't apply. A header describes the length/capacity header
+    /// rather than `T`, so the fingerprint is what tells element types apart.
+    pub fn open_with_options<P: AsRef<Path>>(
+        options: MmapCellOptions<T>,
+        path: P,
+    ) -> Result<MmapVec<T>, MmapCellError> {
+        const { assert!(size_of::<T>() != 0, "MmapVec can't hold zero sized types") };
+
+        let options = options.retype::<VecHeader>();
+
+        // elements are aligned relative to the start of the mapping, like the header is
+        let end = options.data_offset() + size_of::<VecHeader>();
+        let pad = end.next_multiple_of(align_of::<T>()) - end;
+
+        let (cell, remap) = options
+            .resize(false)
+            .size_policy(SizePolicy::ExposeTrailing)
+            .trailing(pad)
+            .open_growable(path)?;
+
+        let vec = MmapVec {
+            cell,
+            remap,
+            pad,
+            _inner: PhantomData,
+        };
+
+        vec.check()?;
+
+        Ok(vec)
+    }
+
+    /// Like [`MmapVec::open`] but with room for at least `capacity` elements.
+    pub fn with_capacity<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+    ) -> Result<MmapVec<T>, MmapCellError> {
+        let mut vec = MmapVec::open(path)?;
+        vec.reserve(capacity.saturating_sub(vec.len()))?;
+
+        Ok(vec)
+    }
+
+    /// Appends `value`, growing the file if it's full.
+    pub fn push(&mut self, value: T) -> Result<(), MmapCellError> {
+        self.reserve(1)?;
+
+        let len = self.len();
+        unsafe { self.data().add(len).write(value) };
+        self.cell.get_mut().len += 1;
+
+        Ok(())
+    }
+
+    /// Removes the last element and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.len().checked_sub(1)?;
+        self.cell.get_mut().len = len as u64;
+
+        // SAFETY: was in bounds a moment ago and T is MmapSafe
+        Some(unsafe { self.data().add(len).read() })
+    }
+
+    /// Makes room for at least `additional` more elements, growing the file
+    /// to at least double its capacity if it needs to grow at all.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), MmapCellError> {
+        let overflow = || std::io::Error::new(std::io::ErrorKind::OutOfMemory, "capacity overflow");
+
+        let needed = self.len().checked_add(additional).ok_or_else(overflow)?;
+
+        if needed <= self.capacity() {
+            return Ok(());
+        }
+
+        let capacity = needed.max(self.capacity().saturating_mul(2)).max(8);
+        let trailing = capacity
+            .checked_mul(size_of::<T>())
+            .and_then(|len| len.checked_add(self.pad))
+            .ok_or_else(overflow)?;
+
+        self.remap.grow(&mut self.cell, trailing)?;
+        self.cell.get_mut().capacity = capacity as u64;
+
+        Ok(())
+    }
+
+    /// Checks that the header agrees with the size of the file.
+    fn check(&self) -> Result<(), MmapCellError> {
+        let (len, capacity) = (self.len(), self.capacity());
+
+        // the length/capacity header itself was checked for when mapping
+        let expected = capacity
+            .checked_mul(size_of::<T>())
+            .and_then(|len| len.checked_add(size_of::<VecHeader>() + self.pad))
+            .unwrap_or(usize::MAX);
+        let actual = size_of::<VecHeader>() + self.cell.trailing;
+
+        if actual < expected {
+            return Err(LayoutError::TooSmall { expected, actual }.into());
+        }
+
+        if len > capacity {
+            return Err(LayoutError::LengthOutOfBounds { len, capacity }.into());
+        }
+
+        let addr = self.data() as usize;
+        if !addr.is_multiple_of(align_of::<T>()) {
+            return Err(LayoutError::Misaligned {
+                align: align_of::<T>(),
+                addr,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: MmapSafe + Copy> MmapVec<T> {
+    /// Appends every element of `values`, growing the file at most once.
+    pub fn extend_from_slice(&mut self, values: &[T]) -> Result<(), MmapCellError> {
+        self.reserve(values.len())?;
+
+        let len = self.len();
+        unsafe {
+            self.data()
+                .add(len)
+                .copy_from_nonoverlapping(values.as_ptr(), values.len())
+        };
+        self.cell.get_mut().len += values.len() as u64;
+
+        Ok(())
+    }
+}
+
+impl<T> MmapVec<T> {
+    /// where the elements start, the mapping holds at least this much
+    fn data(&self) -> *mut T {
+        unsafe { self.cell.trailing_ptr().add(self.pad).cast::<T>() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cell.get().len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many elements fit before the file has to grow.
+    pub fn capacity(&self) -> usize {
+        self.cell.get().capacity as usize
+    }
+
+    /// Shortens the vector to `len` elements, the file keeps its size.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.cell.get_mut().len = len as u64;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: len was checked against the file size on open and is only
+        // ever raised after the elements below it were written
+        unsafe { std::slice::from_raw_parts(self.data(), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.data(), self.len()) }
+    }
+
+    /// Writes dirty pages back to the file and waits for it to finish.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        self.cell.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vec_persists_and_grows() {
+        let path = std::env::temp_dir().join("mmapcell-vec-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let mut vec = MmapVec::<u32>::open(&path).unwrap();
+        assert!(vec.is_empty());
+
+        for i in 0..100 {
+            vec.push(i).unwrap();
+        }
+
+        assert_eq!(vec.len(), 100);
+        assert!(vec.capacity() >= 100);
+        assert_eq!(vec.pop(), Some(99));
+
+        vec[0] = 1000;
+        drop(vec);
+
+        let mut vec = MmapVec::<u32>::open(&path).unwrap();
+        assert_eq!(vec.len(), 99);
+        assert_eq!(vec[0], 1000);
+        assert_eq!(vec.iter().skip(1).copied().sum::<u32>(), (1..99).sum());
+
+        vec.truncate(2);
+        vec.extend_from_slice(&[7; 3]).unwrap();
+        assert_eq!(&vec[..], &[1000, 1, 7, 7, 7]);
+        drop(vec);
+
+        // a header claiming more than the file holds is rejected
+        let mut raw = std::fs::read(&path).unwrap();
+        raw[8..16].copy_from_slice(&u64::MAX.to_ne_bytes());
+        std::fs::write(&path, raw).unwrap();
+
+        assert!(matches!(
+            MmapVec::<u32>::open(&path),
+            Err(MmapCellError::Layout(LayoutError::TooSmall { .. }))
+        ));
+
+        // and so is a file cut off partway through the header
+        std::fs::write(&path, [0; 8]).unwrap();
+
+        assert!(matches!(
+            MmapVec::<u32>::open(&path),
+            Err(MmapCellError::Layout(LayoutError::TooSmall {
+                expected: 16,
+                actual: 8
+            }))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn vec_with_options() {
+        use crate::{HeaderError, LockMode};
+        use std::time::Duration;
+
+        let path = std::env::temp_dir().join("mmapcell-vec-options-test.bin");
+        let _ = std::fs::remove_file(&path);
+
+        let options = || {
+            MmapCell::<u128>::options()
+                .create(true)
+                .header(5)
+                .lock(LockMode::TryExclusive(Duration::ZERO))
+        };
+
+        let mut vec = MmapVec::open_with_options(options(), &path).unwrap();
+        vec.extend_from_slice(&[1, 2, 3]).unwrap();
+
+        // growing keeps the lock, the header and the elements
+        for i in 0..100 {
+            vec.push(i).unwrap();
+        }
+
+        assert!(matches!(
+            MmapVec::open_with_options(options(), &path),
+            Err(MmapCellError::Locked)
+        ));
+        drop(vec);
+
+        assert!(matches!(
+            MmapVec::open_with_options(options().header(6), &path),
+            Err(MmapCellError::Header(
+                HeaderError::FingerprintMismatch { .. }
+            ))
+        ));
+
+        let vec = MmapVec::open_with_options(options(), &path).unwrap();
+        assert_eq!(vec.len(), 103);
+        assert_eq!(vec[..3], [1, 2, 3]);
+        assert_eq!(vec[102], 99);
+        assert!(vec.as_ptr().is_aligned());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
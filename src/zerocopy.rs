@@ -0,0 +1,98 @@
+//! Safe constructors and byte views for [`zerocopy`] types.
+//!
+//! Enabled with the `zerocopy` feature. These mirror the [`MmapCell`] constructors
+//! for types that already derive `FromBytes`/`AsBytes` instead of [`MmapSafe`](crate::MmapSafe).
+//!
+//! ```rust
+//! use mmapcell::zerocopy;
+//!
+//! let mut cell = zerocopy::new_anon::<[u32; 4]>().unwrap();
+//! cell.get_mut()[0] = 1;
+//!
+//! assert_eq!(zerocopy::bytes_of(&cell)[..4], 1u32.to_ne_bytes());
+//! ```
+
+use ::zerocopy::{AsBytes, FromBytes};
+use memmap2::MmapOptions;
+use std::path::Path;
+
+use crate::{MmapCell, MmapCellError, SizePolicy};
+
+/// Like [`MmapCell::new_anon`] for a `FromBytes` type.
+pub fn new_anon<T: FromBytes>() -> Result<MmapCell<T>, MmapCellError> {
+    let m = MmapOptions::new().len(size_of::<T>()).map_anon()?;
+
+    // SAFETY: FromBytes types are valid for any bytes
+    unsafe { MmapCell::new_unchecked(m, SizePolicy::default()) }
+}
+
+/// Like [`MmapCell::new_named`] for a `FromBytes` type.
+pub fn new_named<T: FromBytes, P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+    let options = MmapCell::options().create(true).resize(true);
+
+    // SAFETY: FromBytes types are valid for any bytes
+    unsafe { options.open_unchecked(path) }
+}
+
+/// Like [`MmapCell::open_named`] for a `FromBytes` type.
+pub fn open_named<T: FromBytes, P: AsRef<Path>>(path: P) -> Result<MmapCell<T>, MmapCellError> {
+    open_named_with_policy(path, SizePolicy::default())
+}
+
+/// Like [`MmapCell::open_named_with_policy`] for a `FromBytes` type.
+pub fn open_named_with_policy<T: FromBytes, P: AsRef<Path>>(
+    path: P,
+    policy: SizePolicy,
+) -> Result<MmapCell<T>, MmapCellError> {
+    let options = MmapCell::options().size_policy(policy);
+
+    // SAFETY: FromBytes types are valid for any bytes
+    unsafe { options.open_unchecked(path) }
+}
+
+/// The bytes of the mapped `T`.
+pub fn bytes_of<T: AsBytes>(cell: &MmapCell<T>) -> &[u8] {
+    // SAFETY: AsBytes types have no padding
+    unsafe { cell.bytes_unchecked() }
+}
+
+/// The bytes of the mapped `T`, writable.
+pub fn bytes_of_mut<T: AsBytes + FromBytes>(cell: &mut MmapCell<T>) -> &mut [u8] {
+    // SAFETY: AsBytes types have no padding and FromBytes types are valid for any bytes
+    unsafe { cell.bytes_mut_unchecked() }
+}
+
+/// Reinterprets a cell as another type of the same size,
+/// most usefully to and from a `[u8; N]` byte view.
+///
+/// Fails to compile if `A` and `B` differ in size and returns an
+/// error (dropping the cell) if the mapping isn't aligned for `B`.
+pub fn cast<A: AsBytes, B: FromBytes>(cell: MmapCell<A>) -> Result<MmapCell<B>, MmapCellError> {
+    // SAFETY: A has no padding so its bytes are initialized and B is valid for any bytes
+    unsafe { cell.cast_unchecked() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_bytes_byte_view_roundtrip() {
+        let path = std::env::temp_dir().join("mmapcell-zerocopy-test.bin");
+
+        let mut cell = new_named::<u64, _>(&path).unwrap();
+        *cell.get_mut() = 0x0102_0304_0506_0708;
+
+        let mut bytes = cast::<_, [u8; 8]>(cell).unwrap();
+        assert_eq!(*bytes.get(), 0x0102_0304_0506_0708u64.to_ne_bytes());
+
+        bytes.get_mut()[0] ^= 0xff;
+        drop(bytes);
+
+        let mut cell = open_named::<u64, _>(&path).unwrap();
+        bytes_of_mut(&mut cell)[0] ^= 0xff;
+        assert_eq!(*cell.get(), 0x0102_0304_0506_0708);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}